@@ -0,0 +1,59 @@
+//! Timings of the TOON parser alone, without PHP, for the shapes listed under
+//! "Pathological Inputs" in docs/BENCHMARKS.md.
+//!
+//! ```bash
+//! cargo bench --no-default-features --bench parse
+//! ```
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use php_rs_toon::toon::{parse_with, ParseOptions};
+
+fn nested(depth: usize) -> String {
+    format!("value: {}x{}", "[".repeat(depth), "]".repeat(depth))
+}
+
+fn long_list(len: usize) -> String {
+    let items: Vec<String> = (1..=len).map(|i| format!("[{i}, \"s{i}\", 1.5]")).collect();
+    format!("value: {}", items.join(", "))
+}
+
+fn table(rows: usize) -> String {
+    let mut out = format!("rows[{rows}]{{id,name,score}}:\n");
+    for i in 1..=rows {
+        out.push_str(&format!("  {i},name{i},{i}.5\n"));
+    }
+    out
+}
+
+/// Median time of one parse of `input` over `iterations` runs.
+fn time(input: &str, opts: &ParseOptions, iterations: usize) -> Duration {
+    let mut samples: Vec<Duration> = (0..iterations)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(parse_with(black_box(input), opts).unwrap());
+            start.elapsed()
+        })
+        .collect();
+    samples.sort();
+    samples[iterations / 2]
+}
+
+fn main() {
+    let opts = ParseOptions {
+        max_depth: 1000,
+        ..ParseOptions::default()
+    };
+    let cases = [
+        ("50 nested `[...]`", nested(50), 1000),
+        ("500 nested `[...]`", nested(500), 200),
+        ("1000 nested `[...]`", nested(999), 100),
+        ("Inline list of 10,000 small lists", long_list(10_000), 50),
+        ("Table of 10,000 rows", table(10_000), 50),
+    ];
+    for (name, input, iterations) in &cases {
+        let median = time(input, &opts, *iterations);
+        println!("| {name} | {:.3}ms |", median.as_secs_f64() * 1000.0);
+    }
+}
@@ -16,6 +16,8 @@ class ToonBench
     private string $smallToon;
     private string $mediumToon;
     private string $largeToon;
+    private string $nestedToon;
+    private string $longListToon;
 
     public function __construct()
     {
@@ -68,6 +70,14 @@ class ToonBench
         $this->smallToon = Toon::encode($this->smallData);
         $this->mediumToon = Toon::encode($this->mediumData);
         $this->largeToon = Toon::encode($this->largeData);
+
+        // Pathological inputs for the parser: deeply nested inline lists and
+        // one very long inline list of small lists
+        $this->nestedToon = 'value: ' . str_repeat('[', 500) . 'x' . str_repeat(']', 500);
+        $this->longListToon = 'value: ' . implode(', ', array_map(
+            fn ($i) => "[$i, \"s$i\", 1.5]",
+            range(1, 10000)
+        ));
     }
 
     #[Bench\BeforeMethods('__construct')]
@@ -79,6 +89,15 @@ class ToonBench
         toon_encode($this->smallData);
     }
 
+    #[Bench\BeforeMethods('__construct')]
+    #[Bench\Revs(1000)]
+    #[Bench\Iterations(5)]
+    #[Bench\OutputTimeUnit('milliseconds', precision: 3)]
+    public function benchSmallEncodeRustTree(): void
+    {
+        toon_encode($this->smallData, ['tree' => true]);
+    }
+
     #[Bench\BeforeMethods('__construct')]
     #[Bench\Revs(1000)]
     #[Bench\Iterations(5)]
@@ -115,6 +134,15 @@ class ToonBench
         toon_encode($this->mediumData);
     }
 
+    #[Bench\BeforeMethods('__construct')]
+    #[Bench\Revs(100)]
+    #[Bench\Iterations(5)]
+    #[Bench\OutputTimeUnit('milliseconds', precision: 3)]
+    public function benchMediumEncodeRustTree(): void
+    {
+        toon_encode($this->mediumData, ['tree' => true]);
+    }
+
     #[Bench\BeforeMethods('__construct')]
     #[Bench\Revs(100)]
     #[Bench\Iterations(5)]
@@ -151,6 +179,15 @@ class ToonBench
         toon_encode($this->largeData);
     }
 
+    #[Bench\BeforeMethods('__construct')]
+    #[Bench\Revs(10)]
+    #[Bench\Iterations(5)]
+    #[Bench\OutputTimeUnit('milliseconds', precision: 3)]
+    public function benchLargeEncodeRustTree(): void
+    {
+        toon_encode($this->largeData, ['tree' => true]);
+    }
+
     #[Bench\BeforeMethods('__construct')]
     #[Bench\Revs(10)]
     #[Bench\Iterations(5)]
@@ -177,4 +214,22 @@ class ToonBench
     {
         Toon::decode($this->largeToon);
     }
+
+    #[Bench\BeforeMethods('__construct')]
+    #[Bench\Revs(100)]
+    #[Bench\Iterations(5)]
+    #[Bench\OutputTimeUnit('milliseconds', precision: 3)]
+    public function benchNestedListDecodeRust(): void
+    {
+        toon_decode($this->nestedToon, ['max_depth' => 1000]);
+    }
+
+    #[Bench\BeforeMethods('__construct')]
+    #[Bench\Revs(10)]
+    #[Bench\Iterations(5)]
+    #[Bench\OutputTimeUnit('milliseconds', precision: 3)]
+    public function benchLongListDecodeRust(): void
+    {
+        toon_decode($this->longListToon);
+    }
 }
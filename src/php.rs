@@ -0,0 +1,960 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::{mem, ptr};
+
+use ext_php_rs::boxed::ZBox;
+use ext_php_rs::convert::IntoZval;
+use ext_php_rs::error::Error;
+use ext_php_rs::ffi::zend_hash_index_update;
+use ext_php_rs::flags::ClassFlags;
+use ext_php_rs::internal::function::PhpFunction;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ArrayKey, ZendCallable, ZendHashTable, ZendObject, ZendStr, Zval};
+use ext_php_rs::zend::{ClassEntry, ExecutorGlobals, ModuleEntry};
+use ext_php_rs::{info_table_end, info_table_header, info_table_row, info_table_start};
+
+use crate::toon::{self, ToonMap, ToonValue};
+
+mod builder;
+mod classes;
+mod emit;
+mod errors;
+mod input;
+mod options;
+mod output;
+mod pdo;
+mod traversable;
+use classes::{Decoder, Document, Encoder, RowIterator};
+use errors::toon_exception;
+use options::{DecodeOptions, EncodeOptions, UnsupportedPolicy};
+use output::ZendString;
+
+#[php_function]
+#[php(name = "toon_decode")]
+pub fn rust_toon_decode(input: String, options: Option<&ZendHashTable>) -> PhpResult<Zval> {
+    decode(&input, &DecodeOptions::from_php(options)?)
+}
+
+/// Decodes only the value at `path` (`settings.llm.model`, `users[0].name`),
+/// skipping the rest of the document without building it.
+#[php_function]
+#[php(name = "toon_decode_path")]
+pub fn rust_toon_decode_path(
+    input: String,
+    path: String,
+    options: Option<&ZendHashTable>,
+) -> PhpResult<Zval> {
+    let options = DecodeOptions::from_ini().merge("toon_decode_path()", options)?;
+    check_input_size(input.len(), &options)?;
+    let val = toon::parse_path(&input, &path, &options.parse)
+        .map_err(toon_exception)?
+        .ok_or_else(|| PhpException::default(format!("No value at path '{path}'")))?;
+
+    // Path-based `enums` and `dates` options keep matching from the root
+    let path = toon::split_path(&path)
+        .map_err(toon_exception)?
+        .into_iter()
+        .map(|segment| match segment {
+            toon::PathSegment::Key(key) => key,
+            toon::PathSegment::Index(idx) => idx.to_string(),
+        })
+        .collect();
+    toon_value_to_zval_at(val, path, &options)
+}
+
+/// Decodes a file without first loading it into a PHP string. Large local
+/// files are memory-mapped; stream wrapper URLs and `open_basedir`
+/// restricted setups go through PHP's stream layer instead.
+#[php_function]
+#[php(name = "toon_decode_file")]
+pub fn rust_toon_decode_file(path: String, options: Option<&ZendHashTable>) -> PhpResult<Zval> {
+    let options = DecodeOptions::from_ini().merge("toon_decode_file()", options)?;
+    let uses_php_streams = path.contains("://")
+        || options::ini_value("open_basedir").is_some_and(|dirs| !dirs.is_empty());
+
+    let contents = if uses_php_streams {
+        let stream = ZendCallable::try_from_name("fopen")?
+            .try_call(vec![&path, &"rb"])
+            .map_err(php_error)?;
+        if !stream.is_resource() {
+            return Err(PhpException::default(format!("Could not open '{path}'")));
+        }
+        let contents = input::read_stream(&stream, options.max_input_size);
+        ZendCallable::try_from_name("fclose")?
+            .try_call(vec![&stream])
+            .map_err(php_error)?;
+        contents?
+    } else {
+        input::read_file(&path)
+            .map_err(|e| PhpException::default(format!("Could not read '{path}': {e}")))?
+    };
+    decode_bytes(&contents, &options)
+}
+
+/// Decodes everything remaining in a PHP stream resource.
+#[php_function]
+#[php(name = "toon_decode_stream")]
+pub fn rust_toon_decode_stream(stream: &Zval, options: Option<&ZendHashTable>) -> PhpResult<Zval> {
+    let options = DecodeOptions::from_ini().merge("toon_decode_stream()", options)?;
+    if !stream.is_resource() {
+        return Err(PhpException::default(
+            "toon_decode_stream() expects a stream resource".to_string(),
+        ));
+    }
+    let contents = input::read_stream(stream, options.max_input_size)?;
+    decode_bytes(&contents, &options)
+}
+
+/// Returns a `Generator` over the rows of the tabular array at `path` (see
+/// `toon::split_path`, `""` for a root array) of a TOON string or stream.
+/// Rows are parsed as they are requested, so memory use does not grow with
+/// the number of rows.
+#[php_function]
+#[php(name = "toon_decode_rows")]
+pub fn rust_toon_decode_rows(
+    source: &Zval,
+    path: String,
+    options: Option<&ZendHashTable>,
+) -> PhpResult<Zval> {
+    let options = DecodeOptions::from_ini().merge("toon_decode_rows()", options)?;
+    let input: Box<dyn BufRead> = if let Some(reader) = input::StringReader::new(source) {
+        Box::new(reader)
+    } else if source.is_resource() {
+        Box::new(BufReader::with_capacity(
+            input::STREAM_CHUNK_SIZE,
+            input::StreamReader::new(source)?,
+        ))
+    } else {
+        return Err(PhpException::default(
+            "toon_decode_rows() expects a string or a stream resource".to_string(),
+        ));
+    };
+
+    let rows = toon::RowReader::new(input, &path, options.parse.clone()).map_err(toon_exception)?;
+    RowIterator::new(rows, options).into_generator()
+}
+
+#[php_function]
+#[php(name = "toon_encode")]
+pub fn rust_toon_encode(input: &Zval, options: Option<&ZendHashTable>) -> PhpResult<ZendString> {
+    encode(input, &EncodeOptions::from_php(options)?)
+}
+
+/// Encodes straight into a PHP stream resource, writing buffered chunks as
+/// the document is produced instead of building the whole string first.
+/// Returns the number of bytes written.
+#[php_function]
+#[php(name = "toon_encode_to_stream")]
+pub fn rust_toon_encode_to_stream(
+    input: &Zval,
+    stream: &Zval,
+    options: Option<&ZendHashTable>,
+) -> PhpResult<usize> {
+    let options = EncodeOptions::from_ini().merge("toon_encode_to_stream()", options)?;
+    if !stream.is_resource() {
+        return Err(PhpException::default(
+            "toon_encode_to_stream() expects a stream resource".to_string(),
+        ));
+    }
+
+    let mut writer = output::StreamWriter::new(stream)?;
+    let mut encoder = toon::StreamEncoder::new(
+        BufWriter::with_capacity(output::STREAM_BUFFER_SIZE, &mut writer),
+        options.format.clone(),
+    );
+    // Lengthless headers let Traversable rows go out as they are produced;
+    // otherwise arrays are written straight from the PHP values
+    let result = if options.format.lengthless {
+        traversable::stream(input, &options, &mut encoder)
+    } else {
+        emit::encode_to_stream(input, &options, &mut encoder)
+    }
+    .and_then(|()| {
+        encoder
+            .finish()
+            .map_err(|e| PhpException::default(e.to_string()))
+    });
+    if let Some(exception) = writer.exception.take() {
+        return Err(exception);
+    }
+    result?;
+    Ok(writer.written)
+}
+
+/// Encodes the rows of an executed `PDOStatement` as a table, fetching them
+/// one at a time instead of going through `fetchAll()`.
+#[php_function]
+#[php(name = "toon_encode_pdo")]
+pub fn rust_toon_encode_pdo(
+    stmt: &ZendObject,
+    options: Option<&ZendHashTable>,
+) -> PhpResult<ZendString> {
+    let options = EncodeOptions::from_ini().merge("toon_encode_pdo()", options)?;
+    pdo::encode_statement(stmt, &options)
+}
+
+/// Returns the paths substituted by the last `toon_encode()` call under the
+/// `null` or converter `unsupported` policies.
+#[php_function]
+#[php(name = "toon_last_substitutions")]
+pub fn rust_toon_last_substitutions() -> Vec<String> {
+    LAST_SUBSTITUTIONS.with_borrow(Clone::clone)
+}
+
+/// Returns the version of the loaded extension build.
+#[php_function]
+#[php(name = "toon_version")]
+pub fn rust_toon_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// Returns the optional capabilities compiled into this build.
+#[php_function]
+#[php(name = "toon_features")]
+pub fn rust_toon_features() -> Vec<String> {
+    FEATURES.iter().map(|f| f.to_string()).collect()
+}
+
+pub fn startup(_ty: i32, module_number: i32) -> i32 {
+    options::register_ini_entries(module_number);
+    0
+}
+
+extern "C" fn request_shutdown(_ty: i32, _module_number: i32) -> i32 {
+    classes::release_row_generator();
+    0
+}
+
+#[php_module]
+#[php(startup = "startup")]
+pub fn get_module(module: ModuleBuilder) -> ModuleBuilder {
+    errors::register_constants(module)
+        .function((_internal_rust_toon_decode::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_decode_path::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_decode_file::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_decode_stream::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_decode_rows::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_encode::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_encode_to_stream::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_encode_pdo::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_last_substitutions::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_version::FUNCTION_ENTRY)())
+        .function((_internal_rust_toon_features::FUNCTION_ENTRY)())
+        .class::<Encoder>()
+        .class::<Decoder>()
+        .class::<RowIterator>()
+        .class::<Document>()
+        .request_shutdown_function(request_shutdown)
+        .info_function(php_module_info)
+}
+
+/// Prints the extension's section of `phpinfo()` / `php -i`.
+pub extern "C" fn php_module_info(_module: *mut ModuleEntry) {
+    info_table_start!();
+    info_table_row!("toon support", "enabled");
+    info_table_row!("Version", env!("CARGO_PKG_VERSION"));
+    info_table_row!("TOON spec version", toon::SPEC_VERSION);
+    info_table_row!("Default max depth", options::DEFAULT_MAX_DEPTH.to_string());
+    info_table_row!("Parser depth limit", toon::MAX_PARSE_DEPTH.to_string());
+    info_table_row!("Max depth ceiling", toon::MAX_DEPTH_LIMIT.to_string());
+    info_table_row!("Features", FEATURES.join(", "));
+    info_table_end!();
+
+    info_table_start!();
+    info_table_header!("Directive", "Value", "Default");
+    for (name, default) in options::INI_ENTRIES {
+        let value = options::ini_value(name).unwrap_or_default();
+        info_table_row!(name, value, default);
+    }
+    info_table_end!();
+}
+
+// --- Helpers ---
+
+fn decode(input: &str, options: &DecodeOptions) -> PhpResult<Zval> {
+    check_input_size(input.len(), options)?;
+    builder::decode(input, options)
+}
+
+/// Parses a whole document, for callers that keep the `ToonValue` tree.
+fn parse_document(input: &str, options: &DecodeOptions) -> PhpResult<ToonValue> {
+    check_input_size(input.len(), options)?;
+    toon::parse_with(input, &options.parse).map_err(toon_exception)
+}
+
+fn check_input_size(len: usize, options: &DecodeOptions) -> PhpResult<()> {
+    if options.max_input_size > 0 && len > options.max_input_size {
+        return Err(PhpException::default(format!(
+            "Input of {} bytes exceeds the maximum size of {} bytes",
+            len, options.max_input_size
+        )));
+    }
+    Ok(())
+}
+
+fn decode_bytes(input: &[u8], options: &DecodeOptions) -> PhpResult<Zval> {
+    let input = std::str::from_utf8(input)
+        .map_err(|e| PhpException::default(format!("Input is not valid UTF-8: {e}")))?;
+    decode(input, options)
+}
+
+fn encode(input: &Zval, options: &EncodeOptions) -> PhpResult<ZendString> {
+    if options.tree {
+        let val = zval_to_toon_value(input, options)?;
+        return Ok(toon::encode_with(&val, &options.format).into());
+    }
+    emit::encode(input, options)
+}
+
+/// Capabilities reported by `toon_features()` and `phpinfo()`.
+const FEATURES: &[&str] = &[
+    "delimiters",
+    "strict",
+    "ini",
+    "enums",
+    "dates",
+    "references",
+    "unsupported-policy",
+    "classes",
+    "files",
+    "streams",
+    "tabular",
+    "rows",
+    "traversable",
+    "pdo",
+    "paths",
+    "documents",
+];
+
+thread_local! {
+    static LAST_SUBSTITUTIONS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// State threaded through a single `toon_decode()` call.
+struct DecodeContext<'a> {
+    options: &'a DecodeOptions,
+    /// Key path of the value being converted; only maintained when an option
+    /// needs it.
+    path: Vec<String>,
+    keys: KeyCache,
+}
+
+impl<'a> DecodeContext<'a> {
+    fn new(options: &'a DecodeOptions, path: Vec<String>) -> Self {
+        Self {
+            options,
+            path,
+            keys: KeyCache::default(),
+        }
+    }
+}
+
+/// One zend string per distinct map key of a decode, shared by every array
+/// and object using it instead of being allocated again for each row.
+#[derive(Default)]
+struct KeyCache(HashMap<Box<str>, ZBox<ZendStr>>);
+
+impl KeyCache {
+    fn get(&mut self, key: &str) -> *mut ZendStr {
+        if let Some(cached) = self.0.get_mut(key) {
+            return ptr::from_mut(&mut **cached);
+        }
+        let mut cached = ZendStr::new(key, false);
+        let ptr = ptr::from_mut(&mut *cached);
+        self.0.insert(key.into(), cached);
+        ptr
+    }
+}
+
+extern "C" {
+    fn zend_hash_real_init_packed(ht: *mut ZendHashTable);
+    fn zend_hash_next_index_insert_new(ht: *mut ZendHashTable, data: *mut Zval) -> *mut Zval;
+    fn zend_hash_update(ht: *mut ZendHashTable, key: *mut ZendStr, data: *mut Zval) -> *mut Zval;
+}
+
+/// Converts one of several values of the same decode, such as the rows of a
+/// table, sharing the key strings of `keys` with the values before it.
+fn toon_value_to_zval(
+    val: ToonValue,
+    options: &DecodeOptions,
+    keys: &mut KeyCache,
+) -> PhpResult<Zval> {
+    let mut ctx = DecodeContext {
+        options,
+        path: Vec::new(),
+        keys: mem::take(keys),
+    };
+    let result = toon_value_to_zval_impl(val, 0, &mut ctx);
+    *keys = ctx.keys;
+    result
+}
+
+/// Converts a value found at `path` in a larger document.
+fn toon_value_to_zval_at(
+    val: ToonValue,
+    path: Vec<String>,
+    options: &DecodeOptions,
+) -> PhpResult<Zval> {
+    let depth = path.len();
+    let mut ctx = DecodeContext::new(options, path);
+    toon_value_to_zval_impl(val, depth, &mut ctx)
+}
+
+fn toon_value_to_zval_impl(
+    val: ToonValue,
+    depth: usize,
+    ctx: &mut DecodeContext,
+) -> PhpResult<Zval> {
+    // Counted like the parser does, for maps and lists only
+    let nests = matches!(val, ToonValue::Array(_) | ToonValue::Map(_));
+    if nests && depth > ctx.options.parse.max_depth {
+        return Err(PhpException::default(
+            "Recursion depth limit exceeded".to_string(),
+        ));
+    }
+
+    let mut zval = Zval::new();
+    match val {
+        ToonValue::Null => zval.set_null(),
+        ToonValue::Bool(b) => zval.set_bool(b),
+        ToonValue::Int(i) => zval.set_long(i),
+        ToonValue::Float(f) => zval.set_double(f),
+        ToonValue::String(s) => zval.set_string(&s, false)?,
+        ToonValue::Array(arr) => {
+            let ht = build_php_list(arr, depth + 1, ctx)?;
+            zval.set_hashtable(ht);
+        }
+        ToonValue::Map(map) if ctx.options.objects => {
+            zval = build_php_object(map, depth + 1, ctx)?.into_zval(false)?;
+        }
+        ToonValue::Map(map) => {
+            let ht = build_php_map(map, depth + 1, ctx)?;
+            zval.set_hashtable(ht);
+        }
+    }
+    typed_scalar(zval, ctx)
+}
+
+/// Applies the `enums` and `dates` options to a decoded value at `ctx.path`.
+fn typed_scalar(zval: Zval, ctx: &DecodeContext) -> PhpResult<Zval> {
+    if zval.is_long() || zval.is_string() {
+        if let Some(class) = ctx.options.enum_for(&ctx.path) {
+            return enum_case(class, &zval);
+        }
+    }
+    if let Some(s) = zval.str() {
+        if is_iso8601(s) && ctx.options.decodes_date_at(&ctx.path) {
+            return date_from_string(&zval);
+        }
+    }
+    Ok(zval)
+}
+
+/// Matches `YYYY-MM-DD` optionally followed by `THH:MM[:SS[.fff]]` and a
+/// `Z` or `±HH[:MM]` offset.
+fn is_iso8601(s: &str) -> bool {
+    fn digits(b: &[u8]) -> bool {
+        b.iter().all(u8::is_ascii_digit)
+    }
+
+    let b = s.as_bytes();
+    if b.len() < 10
+        || !digits(&b[0..4])
+        || b[4] != b'-'
+        || !digits(&b[5..7])
+        || b[7] != b'-'
+        || !digits(&b[8..10])
+    {
+        return false;
+    }
+    let rest = &b[10..];
+    if rest.is_empty() {
+        return true;
+    }
+
+    // Time part
+    if rest.len() < 6
+        || rest[0] != b'T'
+        || !digits(&rest[1..3])
+        || rest[3] != b':'
+        || !digits(&rest[4..6])
+    {
+        return false;
+    }
+    let mut i = 6;
+    if rest.get(i) == Some(&b':') {
+        if rest.len() < i + 3 || !digits(&rest[i + 1..i + 3]) {
+            return false;
+        }
+        i += 3;
+        if rest.get(i) == Some(&b'.') {
+            i += 1;
+            let start = i;
+            while rest.get(i).is_some_and(u8::is_ascii_digit) {
+                i += 1;
+            }
+            if i == start {
+                return false;
+            }
+        }
+    }
+
+    // Offset
+    match &rest[i..] {
+        [] | [b'Z'] => true,
+        [b'+' | b'-', h1, h2] => digits(&[*h1, *h2]),
+        [b'+' | b'-', h1, h2, b':', m1, m2] | [b'+' | b'-', h1, h2, m1, m2] => {
+            digits(&[*h1, *h2, *m1, *m2])
+        }
+        _ => false,
+    }
+}
+
+fn date_from_string(value: &Zval) -> PhpResult<Zval> {
+    let date = ZendCallable::try_from_name("date_create_immutable")?
+        .try_call(vec![value])
+        .map_err(php_error)?;
+    if date.is_object() {
+        Ok(date)
+    } else {
+        Err(PhpException::default(format!(
+            "Invalid date '{}'",
+            value.str().unwrap_or_default()
+        )))
+    }
+}
+
+/// Resolves a decoded scalar to a case of `class`: backed enums go through
+/// `from()`, pure enums are looked up by case name.
+fn enum_case(class: &str, value: &Zval) -> PhpResult<Zval> {
+    let ce = ClassEntry::try_find(class)
+        .filter(|ce| ce.flags().contains(ClassFlags::Enum))
+        .ok_or_else(|| PhpException::default(format!("'{class}' is not an enum")))?;
+    let is_backed = ClassEntry::try_find("BackedEnum").is_some_and(|backed| ce.instance_of(backed));
+
+    let result = if is_backed {
+        ZendCallable::try_from_name(&format!("{class}::from"))?.try_call(vec![value])
+    } else {
+        let name = value.str().ok_or_else(|| {
+            PhpException::default(format!("Pure enum '{class}' needs a case name"))
+        })?;
+        ZendCallable::try_from_name("constant")?.try_call(vec![&format!("{class}::{name}")])
+    };
+    result.map_err(php_error)
+}
+
+/// Converts an ext-php-rs error into an exception, rethrowing the original
+/// PHP exception object when user code threw one.
+fn php_error(err: Error) -> PhpException {
+    match err {
+        Error::Exception(object) => match object.into_zval(false) {
+            Ok(zval) => PhpException::default(String::new()).with_object(zval),
+            Err(e) => e.into(),
+        },
+        other => other.into(),
+    }
+}
+
+/// The exception thrown by PHP code that just ran, if any.
+fn pending_exception() -> Option<PhpException> {
+    ExecutorGlobals::take_exception().map(|object| php_error(Error::Exception(object)))
+}
+
+/// State threaded through a single `toon_encode()` call.
+struct EncodeContext<'a> {
+    options: &'a EncodeOptions,
+    /// `DateTimeInterface`, looked up on the first object encountered.
+    date_interface: Option<Option<&'static ClassEntry>>,
+    /// Arrays and `Traversable`s currently being converted, innermost last.
+    ancestors: Vec<*const ()>,
+    /// Key path of the value being converted.
+    path: Vec<String>,
+    /// Paths of unsupported values replaced under a substitute policy.
+    substitutions: Vec<String>,
+}
+
+impl<'a> EncodeContext<'a> {
+    fn new(options: &'a EncodeOptions) -> Self {
+        Self {
+            options,
+            date_interface: None,
+            ancestors: Vec::new(),
+            path: Vec::new(),
+            substitutions: Vec::new(),
+        }
+    }
+
+    /// Runs `f` with `container` marked as visited, failing if it is already
+    /// one of its own ancestors.
+    fn enter<T>(
+        &mut self,
+        container: *const (),
+        f: impl FnOnce(&mut Self) -> PhpResult<T>,
+    ) -> PhpResult<T> {
+        if self.ancestors.contains(&container) {
+            return Err(PhpException::default(format!(
+                "Circular reference at path {}",
+                self.path.join(".")
+            )));
+        }
+        self.ancestors.push(container);
+        let result = f(self);
+        self.ancestors.pop();
+        result
+    }
+
+    fn is_date(&mut self, obj: &ZendObject) -> bool {
+        let ce = *self
+            .date_interface
+            .get_or_insert_with(|| ClassEntry::try_find("DateTimeInterface"));
+        ce.is_some_and(|ce| obj.instance_of(ce))
+    }
+}
+
+fn zval_to_toon_value(zval: &Zval, options: &EncodeOptions) -> PhpResult<ToonValue> {
+    let mut ctx = EncodeContext::new(options);
+    let result = zval_to_toon_value_impl(zval, 0, &mut ctx);
+    LAST_SUBSTITUTIONS.set(ctx.substitutions);
+    result
+}
+
+fn zval_to_toon_value_impl(
+    zval: &Zval,
+    depth: usize,
+    ctx: &mut EncodeContext,
+) -> PhpResult<ToonValue> {
+    if depth > ctx.options.format.max_depth {
+        return Err(PhpException::default(
+            "Recursion depth limit exceeded".to_string(),
+        ));
+    }
+
+    // PHP references (`&$x`) encode as the value they point to
+    let zval = zval.dereference();
+
+    if zval.is_null() {
+        return Ok(ToonValue::Null);
+    }
+    if zval.is_true() {
+        return Ok(ToonValue::Bool(true));
+    }
+    if zval.is_false() {
+        return Ok(ToonValue::Bool(false));
+    }
+    if zval.is_long() {
+        return Ok(ToonValue::Int(zval.long().unwrap_or(0)));
+    }
+    if zval.is_double() {
+        return Ok(ToonValue::Float(zval.double().unwrap_or(0.0)));
+    }
+    if zval.is_string() {
+        return Ok(ToonValue::String(zval.string().unwrap_or_default()));
+    }
+    if let Some(ht) = zval.array() {
+        return ctx.enter(ptr::from_ref(ht).cast(), |ctx| {
+            hashtable_to_toon_value(ht, depth, ctx)
+        });
+    }
+
+    if let Some(obj) = zval.object() {
+        if obj.get_class_entry().flags().contains(ClassFlags::Enum) {
+            return enum_to_toon_value(obj);
+        }
+        if ctx.is_date(obj) {
+            let formatted = obj
+                .try_call_method("format", vec![&ctx.options.date_format])
+                .map_err(php_error)?;
+            return Ok(ToonValue::String(formatted.string().unwrap_or_default()));
+        }
+        if obj.is_traversable() {
+            return ctx.enter(ptr::from_ref(obj).cast(), |ctx| {
+                traversable::traversable_to_toon_value(zval, depth, ctx)
+            });
+        }
+        // Closures and other objects fall through to the `unsupported` policy
+    }
+
+    unsupported_to_toon_value(zval, depth, ctx)
+}
+
+/// Applies the `unsupported` policy to a value TOON cannot represent.
+fn unsupported_to_toon_value(
+    zval: &Zval,
+    depth: usize,
+    ctx: &mut EncodeContext,
+) -> PhpResult<ToonValue> {
+    let path = ctx.path.join(".");
+    let options = ctx.options;
+    match &options.unsupported {
+        UnsupportedPolicy::Throw => {
+            let type_name = match zval.object() {
+                Some(obj) => obj.get_class_name()?,
+                None => zval.get_type().to_string(),
+            };
+            let location = if path.is_empty() { "(root)" } else { &path };
+            Err(PhpException::default(format!(
+                "Unsupported type {type_name} at path {location}"
+            )))
+        }
+        UnsupportedPolicy::Null => {
+            ctx.substitutions.push(path);
+            Ok(ToonValue::Null)
+        }
+        UnsupportedPolicy::Convert(converter) => {
+            let replacement = ZendCallable::new(converter)?
+                .try_call(vec![zval, &path])
+                .map_err(php_error)?;
+            ctx.substitutions.push(path);
+            zval_to_toon_value_impl(&replacement, depth + 1, ctx)
+        }
+    }
+}
+
+/// Converts a PHP array.
+fn hashtable_to_toon_value(
+    ht: &ZendHashTable,
+    depth: usize,
+    ctx: &mut EncodeContext,
+) -> PhpResult<ToonValue> {
+    let len = ht.len();
+
+    // Detect list candidacy and defer allocating map storage until required
+    let mut expected_idx = 0usize;
+    let mut is_list_candidate = true;
+    let mut list_items: Vec<ToonValue> = Vec::with_capacity(len);
+    let mut map_entries: Option<ToonMap> = None;
+
+    for (k, v) in ht.iter() {
+        let mut treat_as_list_entry = is_list_candidate;
+
+        if treat_as_list_entry {
+            match k {
+                ArrayKey::Long(idx) if idx == expected_idx as i64 => {
+                    expected_idx += 1;
+                }
+                _ => {
+                    treat_as_list_entry = false;
+                    is_list_candidate = false;
+                }
+            }
+        }
+
+        // Scalars never need their key on the path, for error reporting
+        let tracked = !is_scalar(v);
+        if tracked {
+            ctx.path.push(k.to_string());
+        }
+        let val = zval_to_toon_value_impl(v, depth + 1, ctx);
+        if tracked {
+            ctx.path.pop();
+        }
+        let val = val?;
+
+        // If the value is a Map, it cannot be represented in an inline list (in this format),
+        // so the container must become a Map.
+        // Lists containing Lists are fine.
+        if treat_as_list_entry && matches!(val, ToonValue::Map(_)) {
+            treat_as_list_entry = false;
+            is_list_candidate = false;
+        }
+
+        if treat_as_list_entry {
+            list_items.push(val);
+            continue;
+        }
+
+        let entries = map_entries.get_or_insert_with(|| {
+            let mut map = ToonMap::with_capacity(len);
+            for (idx, prev_val) in list_items.drain(..).enumerate() {
+                map.insert(idx.to_string(), prev_val);
+            }
+            map
+        });
+
+        let key_str = match k {
+            ArrayKey::Long(idx) => idx.to_string(),
+            ArrayKey::String(s) => s,
+            ArrayKey::Str(s) => s.to_string(),
+        };
+        entries.insert(key_str, val);
+    }
+
+    if is_list_candidate {
+        return Ok(ToonValue::Array(list_items));
+    }
+
+    if let Some(entries) = map_entries {
+        return Ok(ToonValue::Map(entries));
+    }
+
+    // Empty PHP arrays default to empty lists
+    Ok(ToonValue::Array(Vec::new()))
+}
+
+fn is_scalar(zval: &Zval) -> bool {
+    let zval = zval.dereference();
+    zval.is_null() || zval.is_bool() || zval.is_long() || zval.is_double() || zval.is_string()
+}
+
+/// Backed enum cases encode as their backing value, pure cases as their name.
+fn enum_to_toon_value(obj: &ZendObject) -> PhpResult<ToonValue> {
+    let props = obj.get_properties()?;
+    let case = props
+        .get("value")
+        .or_else(|| props.get("name"))
+        .ok_or_else(|| PhpException::default("Enum case has no name".to_string()))?;
+
+    match case.long() {
+        Some(i) => Ok(ToonValue::Int(i)),
+        None => Ok(ToonValue::String(case.string().unwrap_or_default())),
+    }
+}
+
+fn build_php_list(
+    items: Vec<ToonValue>,
+    depth: usize,
+    ctx: &mut DecodeContext,
+) -> PhpResult<ZBox<ZendHashTable>> {
+    let mut ht = new_php_list(items.len());
+    let tracks_paths = ctx.options.tracks_paths();
+    for (idx, item) in items.into_iter().enumerate() {
+        if tracks_paths {
+            ctx.path.push(idx.to_string());
+        }
+        let child = toon_value_to_zval_impl(item, depth, ctx);
+        if tracks_paths {
+            ctx.path.pop();
+        }
+        list_push(&mut ht, child?)?;
+    }
+    Ok(ht)
+}
+
+/// An empty array set up as a packed list, which [`list_push`] fills in
+/// order without hashing.
+fn new_php_list(len: usize) -> ZBox<ZendHashTable> {
+    let mut ht = ZendHashTable::with_capacity(clamped_capacity(len));
+    unsafe { zend_hash_real_init_packed(&mut *ht) };
+    ht
+}
+
+fn list_push(ht: &mut ZendHashTable, mut child: Zval) -> PhpResult<()> {
+    unsafe {
+        let result = zend_hash_next_index_insert_new(ht, std::ptr::addr_of_mut!(child));
+        if result.is_null() {
+            return Err(PhpException::default(
+                "Failed to insert into PHP list".to_string(),
+            ));
+        }
+    }
+    mem::forget(child);
+    Ok(())
+}
+
+fn build_php_map(
+    entries: ToonMap,
+    depth: usize,
+    ctx: &mut DecodeContext,
+) -> PhpResult<ZBox<ZendHashTable>> {
+    let mut ht = ZendHashTable::with_capacity(clamped_capacity(entries.len()));
+    for (key, value) in entries {
+        let child = map_entry_to_zval(&key, value, depth, ctx)?;
+        map_insert(&mut ht, &key, child, &mut ctx.keys)?;
+    }
+    Ok(ht)
+}
+
+fn map_insert(
+    ht: &mut ZendHashTable,
+    key: &str,
+    mut child: Zval,
+    keys: &mut KeyCache,
+) -> PhpResult<()> {
+    // Optimization: Only attempt to parse as integer if it looks like one.
+    // This avoids expensive parsing for common string keys.
+    let maybe_index =
+        if !key.is_empty() && (key.as_bytes()[0] == b'-' || key.as_bytes()[0].is_ascii_digit()) {
+            key.parse::<i64>().ok()
+        } else {
+            None
+        };
+
+    unsafe {
+        let result = if let Some(idx) = maybe_index {
+            #[allow(clippy::cast_sign_loss)]
+            zend_hash_index_update(ht, idx as u64, std::ptr::addr_of_mut!(child))
+        } else {
+            zend_hash_update(ht, keys.get(key), std::ptr::addr_of_mut!(child))
+        };
+
+        if result.is_null() {
+            return Err(PhpException::default(
+                "Failed to insert into PHP map".to_string(),
+            ));
+        }
+    }
+    mem::forget(child);
+    Ok(())
+}
+
+/// Object-mode counterpart of `build_php_map()`.
+fn build_php_object(
+    entries: ToonMap,
+    depth: usize,
+    ctx: &mut DecodeContext,
+) -> PhpResult<ZBox<ZendObject>> {
+    let mut obj = ZendObject::new_stdclass();
+    for (key, value) in entries {
+        let child = map_entry_to_zval(&key, value, depth, ctx)?;
+        property_insert(&mut obj, &key, child, &mut ctx.keys)?;
+    }
+    Ok(obj)
+}
+
+/// `ZendObject::set_property()` with the name taken from `keys`.
+fn property_insert(
+    obj: &mut ZendObject,
+    key: &str,
+    mut child: Zval,
+    keys: &mut KeyCache,
+) -> PhpResult<()> {
+    let written = unsafe {
+        obj.handlers
+            .as_ref()
+            .and_then(|handlers| handlers.write_property)
+            .map(|write| write(obj, keys.get(key), &mut child, ptr::null_mut()))
+    };
+    if written.is_none_or(<*mut Zval>::is_null) {
+        return Err(PhpException::default(format!(
+            "Failed to set property '{key}'"
+        )));
+    }
+    Ok(())
+}
+
+fn map_entry_to_zval(
+    key: &str,
+    value: ToonValue,
+    depth: usize,
+    ctx: &mut DecodeContext,
+) -> PhpResult<Zval> {
+    let tracks_paths = ctx.options.tracks_paths();
+    if tracks_paths {
+        ctx.path.push(key.to_string());
+    }
+    let child = toon_value_to_zval_impl(value, depth, ctx);
+    if tracks_paths {
+        ctx.path.pop();
+    }
+    child
+}
+
+fn clamped_capacity(len: usize) -> u32 {
+    let max = u32::MAX as usize;
+    if len > max {
+        u32::MAX
+    } else {
+        len as u32
+    }
+}
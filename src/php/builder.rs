@@ -0,0 +1,193 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use ext_php_rs::boxed::ZBox;
+use ext_php_rs::convert::IntoZval;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendHashTable, ZendObject, Zval};
+
+use super::options::DecodeOptions;
+use super::{
+    clamped_capacity, list_push, map_insert, new_php_list, property_insert, typed_scalar,
+    DecodeContext,
+};
+use crate::toon::{self, DuplicateKeys, Scalar, Sink, ToonError};
+
+/// A PHP array or object still receiving values.
+enum Frame {
+    List(ZBox<ZendHashTable>),
+    Map(ZBox<ZendHashTable>),
+    Object(ZBox<ZendObject>),
+}
+
+/// A [`Sink`] building PHP values directly, without a `ToonValue` tree in
+/// between. Produces the same values as `toon_value_to_zval`.
+struct ZvalBuilder<'a> {
+    ctx: DecodeContext<'a>,
+    stack: Vec<Frame>,
+    /// Key of the next value of each open map, reused from one entry to the
+    /// next.
+    keys: Vec<String>,
+    /// Keys already used by each open map, only kept when duplicate keys
+    /// are not simply overwritten.
+    seen: Vec<HashSet<String>>,
+    root: Option<Zval>,
+    /// PHP exception that stopped the parser, rethrown as is.
+    error: Option<PhpException>,
+}
+
+/// Parses `input` straight into PHP values.
+pub fn decode(input: &str, options: &DecodeOptions) -> PhpResult<Zval> {
+    let mut builder = ZvalBuilder {
+        ctx: DecodeContext::new(options, Vec::new()),
+        stack: Vec::new(),
+        keys: Vec::new(),
+        seen: Vec::new(),
+        root: None,
+        error: None,
+    };
+    let result = toon::parse_into(input, &options.parse, &mut builder);
+    if let Some(e) = builder.error {
+        return Err(e);
+    }
+    result.map_err(super::toon_exception)?;
+    Ok(builder.root.unwrap_or_else(Zval::null))
+}
+
+impl ZvalBuilder<'_> {
+    /// Puts the key of a new value on the path. Its depth was already
+    /// checked by the parser, against the same `max_depth`.
+    fn enter(&mut self) {
+        if self.ctx.options.tracks_paths() {
+            let depth = self.stack.len();
+            match self.stack.last() {
+                Some(Frame::List(ht)) => self.ctx.path.push(ht.len().to_string()),
+                Some(_) => self.ctx.path.push(self.keys[depth - 1].clone()),
+                None => {}
+            }
+        }
+    }
+
+    /// Adds a finished value to its parent and takes its key off the path.
+    fn leave(&mut self, value: Zval) -> PhpResult<()> {
+        let depth = self.stack.len();
+        let keep = self.keeps_entry();
+        match self.stack.last_mut() {
+            None => {
+                self.root = Some(value);
+                return Ok(());
+            }
+            Some(Frame::List(ht)) => list_push(ht, value)?,
+            Some(Frame::Map(ht)) if keep => {
+                map_insert(ht, &self.keys[depth - 1], value, &mut self.ctx.keys)?;
+            }
+            Some(Frame::Object(obj)) if keep => {
+                property_insert(obj, &self.keys[depth - 1], value, &mut self.ctx.keys)?;
+            }
+            Some(_) => {}
+        }
+        if self.ctx.options.tracks_paths() {
+            self.ctx.path.pop();
+        }
+        Ok(())
+    }
+
+    /// Whether the value for the pending key of the innermost map is kept:
+    /// only a first one with [`DuplicateKeys::First`]. PHP arrays already let
+    /// the last value win, and `key` rejected repeats for `Error`.
+    fn keeps_entry(&mut self) -> bool {
+        let depth = self.stack.len();
+        if self.ctx.options.parse.duplicate_keys != DuplicateKeys::First
+            || !matches!(self.stack.last(), Some(Frame::Map(_) | Frame::Object(_)))
+        {
+            return true;
+        }
+        self.seen[depth - 1].insert(self.keys[depth - 1].clone())
+    }
+
+    fn begin(&mut self, frame: Frame) {
+        self.enter();
+        self.stack.push(frame);
+        if self.keys.len() < self.stack.len() {
+            self.keys.push(String::new());
+            self.seen.push(HashSet::new());
+        }
+        self.seen[self.stack.len() - 1].clear();
+    }
+
+    fn scalar_zval(&self, value: Scalar<'_>) -> PhpResult<Zval> {
+        let mut zval = Zval::new();
+        match value {
+            Scalar::Null => zval.set_null(),
+            Scalar::Bool(b) => zval.set_bool(b),
+            Scalar::Int(i) => zval.set_long(i),
+            Scalar::Float(f) => zval.set_double(f),
+            Scalar::Str(s) => zval.set_string(&s, false)?,
+        }
+        typed_scalar(zval, &self.ctx)
+    }
+
+    /// Keeps a PHP error for `decode` and stops the parser.
+    fn check(&mut self, result: PhpResult<()>) -> Result<(), ToonError> {
+        result.map_err(|e| {
+            self.error = Some(e);
+            ToonError::sink("Decoding stopped by a PHP error")
+        })
+    }
+}
+
+impl Sink<'_> for ZvalBuilder<'_> {
+    fn begin_map(&mut self, len_hint: usize) -> Result<(), ToonError> {
+        let frame = if self.ctx.options.objects {
+            Frame::Object(ZendObject::new_stdclass())
+        } else {
+            Frame::Map(ZendHashTable::with_capacity(clamped_capacity(len_hint)))
+        };
+        self.begin(frame);
+        Ok(())
+    }
+
+    fn begin_list(&mut self, len_hint: usize) -> Result<(), ToonError> {
+        let frame = Frame::List(new_php_list(len_hint));
+        self.begin(frame);
+        Ok(())
+    }
+
+    fn key(&mut self, key: Cow<'_, str>) -> Result<(), ToonError> {
+        let depth = self.stack.len().wrapping_sub(1);
+        let duplicates = self.ctx.options.parse.duplicate_keys;
+        if duplicates == DuplicateKeys::Error {
+            if let Some(seen) = self.seen.get_mut(depth) {
+                duplicates.check(&key, |key| seen.contains(key))?;
+                seen.insert(key.to_string());
+            }
+        }
+        if let Some(pending) = self.keys.get_mut(depth) {
+            pending.clear();
+            pending.push_str(&key);
+        }
+        Ok(())
+    }
+
+    fn scalar(&mut self, value: Scalar<'_>) -> Result<(), ToonError> {
+        self.enter();
+        let result = self.scalar_zval(value).and_then(|zval| self.leave(zval));
+        self.check(result)
+    }
+
+    fn end(&mut self) -> Result<(), ToonError> {
+        let result = match self.stack.pop() {
+            Some(Frame::List(ht) | Frame::Map(ht)) => {
+                let mut zval = Zval::new();
+                zval.set_hashtable(ht);
+                self.leave(zval)
+            }
+            Some(Frame::Object(obj)) => obj
+                .into_zval(false)
+                .map_err(PhpException::from)
+                .and_then(|zval| self.leave(zval)),
+            None => Ok(()),
+        };
+        self.check(result)
+    }
+}
@@ -0,0 +1,432 @@
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::io::BufRead;
+use std::sync::Arc;
+
+use ext_php_rs::boxed::ZBox;
+use ext_php_rs::convert::IntoZval;
+use ext_php_rs::ffi::{zend_eval_string, ZEND_RESULT_CODE_SUCCESS};
+use ext_php_rs::flags::ClassFlags;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendClassObject, ZendHashTable, ZendObject, Zval};
+use ext_php_rs::zend::{ce, ClassEntry};
+
+use super::options::{DecodeOptions, EncodeOptions};
+use super::output::ZendString;
+use super::KeyCache;
+use crate::toon::{RowReader, ToonValue};
+
+/// Reusable `toon_encode()` configuration. Instances are immutable: every
+/// `with*()` call returns a new encoder.
+#[php_class]
+#[php(name = "Toon\\Encoder")]
+pub struct Encoder {
+    options: EncodeOptions,
+}
+
+#[php_impl]
+impl Encoder {
+    /// Accepts the same options array as `toon_encode()`; missing keys fall
+    /// back to the `toon.*` INI settings.
+    pub fn __construct(options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        Ok(Self {
+            options: EncodeOptions::from_ini().merge(Self::NAME, options)?,
+        })
+    }
+
+    pub fn with_indent(&self, indent: i64) -> PhpResult<ZBox<ZendClassObject<Encoder>>> {
+        self.with("indent", indent)
+    }
+
+    pub fn with_delimiter(&self, delimiter: String) -> PhpResult<ZBox<ZendClassObject<Encoder>>> {
+        self.with("delimiter", delimiter)
+    }
+
+    pub fn with_max_depth(&self, max_depth: i64) -> PhpResult<ZBox<ZendClassObject<Encoder>>> {
+        self.with("max_depth", max_depth)
+    }
+
+    pub fn with_options(
+        &self,
+        options: &ZendHashTable,
+    ) -> PhpResult<ZBox<ZendClassObject<Encoder>>> {
+        let options = self.options.clone().merge(Self::NAME, Some(options))?;
+        Ok(ZendClassObject::new(Self { options }))
+    }
+
+    pub fn encode(&self, value: &Zval) -> PhpResult<ZendString> {
+        super::encode(value, &self.options)
+    }
+}
+
+impl Encoder {
+    const NAME: &'static str = "Toon\\Encoder";
+
+    fn with(&self, name: &str, value: impl IntoZval) -> PhpResult<ZBox<ZendClassObject<Encoder>>> {
+        let mut options = self.options.clone();
+        options.set(Self::NAME, name, &value.into_zval(false)?)?;
+        Ok(ZendClassObject::new(Self { options }))
+    }
+}
+
+/// Reusable `toon_decode()` configuration. Instances are immutable: every
+/// `with*()` call returns a new decoder.
+#[php_class]
+#[php(name = "Toon\\Decoder")]
+pub struct Decoder {
+    options: DecodeOptions,
+}
+
+#[php_impl]
+impl Decoder {
+    /// Accepts the same options array as `toon_decode()`; missing keys fall
+    /// back to the `toon.*` INI settings.
+    pub fn __construct(options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        Ok(Self {
+            options: DecodeOptions::from_ini().merge(Self::NAME, options)?,
+        })
+    }
+
+    pub fn with_delimiter(&self, delimiter: String) -> PhpResult<ZBox<ZendClassObject<Decoder>>> {
+        self.with("delimiter", delimiter)
+    }
+
+    pub fn with_strict(&self, strict: bool) -> PhpResult<ZBox<ZendClassObject<Decoder>>> {
+        self.with("strict", strict)
+    }
+
+    pub fn with_max_depth(&self, max_depth: i64) -> PhpResult<ZBox<ZendClassObject<Decoder>>> {
+        self.with("max_depth", max_depth)
+    }
+
+    /// Decodes maps into `stdClass` objects instead of arrays.
+    pub fn with_object_mode(&self, objects: bool) -> PhpResult<ZBox<ZendClassObject<Decoder>>> {
+        self.with("objects", objects)
+    }
+
+    pub fn with_options(
+        &self,
+        options: &ZendHashTable,
+    ) -> PhpResult<ZBox<ZendClassObject<Decoder>>> {
+        let options = self.options.clone().merge(Self::NAME, Some(options))?;
+        Ok(ZendClassObject::new(Self { options }))
+    }
+
+    pub fn decode(&self, input: String) -> PhpResult<Zval> {
+        super::decode(&input, &self.options)
+    }
+}
+
+impl Decoder {
+    const NAME: &'static str = "Toon\\Decoder";
+
+    fn with(&self, name: &str, value: impl IntoZval) -> PhpResult<ZBox<ZendClassObject<Decoder>>> {
+        let mut options = self.options.clone();
+        options.set(Self::NAME, name, &value.into_zval(false)?)?;
+        Ok(ZendClassObject::new(Self { options }))
+    }
+}
+
+/// Rows of a tabular array, parsed one at a time. `toon_decode_rows()` hands
+/// them out through a `Generator` delegating to this iterator, which is
+/// forward-only and can only be iterated once.
+///
+/// @internal Only created by `toon_decode_rows()`; it has no constructor.
+#[php_class]
+#[php(name = "Toon\\RowIterator")]
+#[php(flags = ClassFlags::Final)]
+#[php(implements(ce = ce::iterator, stub = "\\Iterator"))]
+pub struct RowIterator {
+    rows: RowReader<Box<dyn BufRead>>,
+    options: DecodeOptions,
+    /// Key strings shared by all rows, which repeat the same fields.
+    keys: KeyCache,
+    current: Option<Zval>,
+    key: i64,
+    started: bool,
+}
+
+#[php_impl]
+impl RowIterator {
+    pub fn current(&mut self) -> PhpResult<Zval> {
+        self.start()?;
+        Ok(self
+            .current
+            .as_ref()
+            .map_or_else(Zval::new, Zval::shallow_clone))
+    }
+
+    pub fn key(&mut self) -> PhpResult<Option<i64>> {
+        self.start()?;
+        Ok(self.current.is_some().then_some(self.key))
+    }
+
+    #[php(name = "next")]
+    pub fn next_row(&mut self) -> PhpResult<()> {
+        if self.started {
+            self.key += 1;
+            self.advance()
+        } else {
+            // The first call positions the iterator on row 0, the second moves on
+            self.start()?;
+            self.next_row()
+        }
+    }
+
+    pub fn rewind(&mut self) -> PhpResult<()> {
+        if self.key > 0 {
+            return Err(PhpException::default(
+                "Cannot rewind a Toon\\RowIterator that has already advanced".to_string(),
+            ));
+        }
+        self.start()
+    }
+
+    pub fn valid(&mut self) -> PhpResult<bool> {
+        self.start()?;
+        Ok(self.current.is_some())
+    }
+}
+
+impl RowIterator {
+    pub fn new(rows: RowReader<Box<dyn BufRead>>, options: DecodeOptions) -> Self {
+        Self {
+            rows,
+            options,
+            keys: KeyCache::default(),
+            current: None,
+            key: 0,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) -> PhpResult<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        self.advance()
+    }
+
+    fn advance(&mut self) -> PhpResult<()> {
+        self.current = None;
+        match self.rows.next() {
+            Some(Ok(row)) => {
+                let row = super::toon_value_to_zval(row, &self.options, &mut self.keys)?;
+                self.current = Some(row);
+            }
+            Some(Err(e)) => return Err(super::toon_exception(e)),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Wraps the rows in a PHP `Generator`. Extensions cannot create
+    /// generators themselves, so this calls a closure compiled from
+    /// [`ROW_GENERATOR`] that yields from the iterator.
+    pub fn into_generator(self) -> PhpResult<Zval> {
+        let closure = row_generator()?;
+        let rows = ZendClassObject::new(self).into_zval(false)?;
+        closure.try_call(vec![&rows]).map_err(super::php_error)
+    }
+}
+
+/// The generator function wrapping a [`RowIterator`], keeping its keys.
+const ROW_GENERATOR: &CStr =
+    c"static function (\\Iterator $rows): \\Generator { yield from $rows; }";
+
+thread_local! {
+    /// The closure compiled from [`ROW_GENERATOR`] by the first
+    /// `toon_decode_rows()` call of the request, released at its end.
+    static ROW_GENERATOR_CLOSURE: RefCell<Option<Zval>> = const { RefCell::new(None) };
+}
+
+fn row_generator() -> PhpResult<Zval> {
+    ROW_GENERATOR_CLOSURE.with_borrow_mut(|cached| {
+        if let Some(closure) = cached {
+            return Ok(closure.shallow_clone());
+        }
+        let mut closure = Zval::new();
+        // SAFETY: both strings are NUL-terminated and `closure` receives the
+        // value of the evaluated expression
+        let result = unsafe {
+            zend_eval_string(
+                ROW_GENERATOR.as_ptr(),
+                &raw mut closure,
+                c"toon_decode_rows()".as_ptr(),
+            )
+        };
+        if result != ZEND_RESULT_CODE_SUCCESS || !closure.is_callable() {
+            return Err(super::pending_exception().unwrap_or_else(|| {
+                PhpException::default(
+                    "toon_decode_rows() could not compile its generator function".to_string(),
+                )
+            }));
+        }
+        Ok(cached.insert(closure).shallow_clone())
+    })
+}
+
+/// Drops the cached row generator closure before PHP tears down the
+/// request's objects.
+pub fn release_row_generator() {
+    ROW_GENERATOR_CLOSURE.take();
+}
+
+/// A parsed document kept in Rust memory. Reading an offset converts just
+/// that value: maps and lists come back as further `Toon\Document`s sharing
+/// the same tree, so untouched parts are never turned into PHP values.
+#[php_class]
+#[php(name = "Toon\\Document")]
+#[php(implements(ce = ce::arrayaccess, stub = "\\ArrayAccess"))]
+#[php(implements(ce = ce::aggregate, stub = "\\IteratorAggregate"))]
+#[php(implements(ce = ce::countable, stub = "\\Countable"))]
+#[php(implements(ce = json_serializable, stub = "\\JsonSerializable"))]
+pub struct Document {
+    root: Arc<ToonValue>,
+    /// Positions of the children leading from `root` to this node.
+    node: Vec<usize>,
+    /// Key path of this node, for the path-based decode options.
+    path: Vec<String>,
+    options: Arc<DecodeOptions>,
+}
+
+#[php_impl]
+impl Document {
+    /// Accepts the same options array as `toon_decode()`.
+    pub fn __construct(input: String, options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        let options = DecodeOptions::from_ini().merge(Self::NAME, options)?;
+        Ok(Self {
+            root: Arc::new(super::parse_document(&input, &options)?),
+            node: Vec::new(),
+            path: Vec::new(),
+            options: Arc::new(options),
+        })
+    }
+
+    pub fn offset_exists(&self, offset: &Zval) -> bool {
+        self.position(offset).is_some()
+    }
+
+    /// Returns `null` for a missing offset.
+    pub fn offset_get(&self, offset: &Zval) -> PhpResult<Zval> {
+        match self.position(offset) {
+            Some(idx) => self.child(idx),
+            None => Ok(Zval::new()),
+        }
+    }
+
+    pub fn offset_set(&mut self, _offset: &Zval, _value: &Zval) -> PhpResult<()> {
+        Err(Self::read_only())
+    }
+
+    pub fn offset_unset(&mut self, _offset: &Zval) -> PhpResult<()> {
+        Err(Self::read_only())
+    }
+
+    pub fn count(&self) -> usize {
+        match self.value() {
+            ToonValue::Map(entries) => entries.len(),
+            ToonValue::Array(items) => items.len(),
+            _ => 0,
+        }
+    }
+
+    /// Iterates over the children of this node, converted the same way as
+    /// by `offsetGet()`.
+    pub fn get_iterator(&self) -> PhpResult<ZBox<ZendObject>> {
+        let mut children = ZendHashTable::new();
+        match self.value() {
+            ToonValue::Map(entries) => {
+                for (idx, (key, _)) in entries.iter().enumerate() {
+                    children.insert(key.as_str(), self.child(idx)?)?;
+                }
+            }
+            ToonValue::Array(items) => {
+                for idx in 0..items.len() {
+                    children.push(self.child(idx)?)?;
+                }
+            }
+            _ => {}
+        }
+
+        let ce = ClassEntry::try_find("ArrayIterator")
+            .ok_or_else(|| PhpException::default("Class ArrayIterator not found".to_string()))?;
+        let iterator = ZendObject::new(ce);
+        iterator
+            .try_call_method("__construct", vec![&children])
+            .map_err(super::php_error)?;
+        Ok(iterator)
+    }
+
+    pub fn json_serialize(&self) -> PhpResult<Zval> {
+        self.to_array()
+    }
+
+    /// Converts the whole node, like `toon_decode()` would.
+    pub fn to_array(&self) -> PhpResult<Zval> {
+        super::toon_value_to_zval_at(self.value().clone(), self.path.clone(), &self.options)
+    }
+}
+
+impl Document {
+    const NAME: &'static str = "Toon\\Document";
+
+    fn read_only() -> PhpException {
+        PhpException::default(format!("{} is read-only", Self::NAME))
+    }
+
+    fn value(&self) -> &ToonValue {
+        self.node
+            .iter()
+            .fold(&*self.root, |value, &idx| match value {
+                ToonValue::Map(entries) => &entries[idx],
+                ToonValue::Array(items) => &items[idx],
+                _ => unreachable!("document nodes only descend into containers"),
+            })
+    }
+
+    /// Position of the child at `offset`.
+    fn position(&self, offset: &Zval) -> Option<usize> {
+        let key = match offset.long() {
+            Some(idx) => idx.to_string(),
+            None => offset.string()?,
+        };
+        match self.value() {
+            ToonValue::Map(entries) => entries.get_index_of(&key),
+            ToonValue::Array(items) => key.parse().ok().filter(|&idx| idx < items.len()),
+            _ => None,
+        }
+    }
+
+    fn child(&self, idx: usize) -> PhpResult<Zval> {
+        let (key, value) = match self.value() {
+            ToonValue::Map(entries) => match entries.get_index(idx) {
+                Some((key, value)) => (key.clone(), value),
+                None => return Ok(Zval::new()),
+            },
+            ToonValue::Array(items) => (idx.to_string(), &items[idx]),
+            _ => return Ok(Zval::new()),
+        };
+        let mut path = self.path.clone();
+        path.push(key);
+
+        if matches!(value, ToonValue::Map(_) | ToonValue::Array(_)) {
+            let mut node = self.node.clone();
+            node.push(idx);
+            let child = Self {
+                root: Arc::clone(&self.root),
+                node,
+                path,
+                options: Arc::clone(&self.options),
+            };
+            return Ok(ZendClassObject::new(child).into_zval(false)?);
+        }
+        super::toon_value_to_zval_at(value.clone(), path, &self.options)
+    }
+}
+
+/// `JsonSerializable` lives in ext/json, which is always compiled in.
+fn json_serializable() -> &'static ClassEntry {
+    ClassEntry::try_find("JsonSerializable").expect("ext/json is always available")
+}
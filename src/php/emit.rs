@@ -0,0 +1,326 @@
+use std::fmt::{self, Write};
+use std::{io, iter, ptr};
+
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendHashTable, Zval};
+
+use super::options::EncodeOptions;
+use super::output::{ZendString, ZendStringWriter};
+use super::{is_scalar, zval_to_toon_value_impl, EncodeContext, LAST_SUBSTITUTIONS};
+use crate::toon::{self, Delimiter, ToonValue};
+
+/// Writes `input` as TOON text straight from the PHP values, producing the
+/// same output as `toon::encode_with` on the converted `ToonValue`.
+///
+/// Whether an array is written as a list depends on all of its children, so
+/// the values are walked twice: first to check them and settle the shape of
+/// every array, then to write them into a single PHP string, allocated from
+/// the size estimate of the first walk and returned without a copy.
+pub fn encode(input: &Zval, options: &EncodeOptions) -> PhpResult<ZendString> {
+    let plan = Plan::new(input, options)?;
+    let mut out = ZendStringWriter::with_capacity(plan.size_hint);
+    // Writing into memory cannot fail
+    let _ = plan.write(input, &options.format, &mut out);
+    out.trim_end();
+    Ok(out.finish())
+}
+
+/// Writes `input` like [`encode`] into a stream, chunk by chunk, so only the
+/// plan of its arrays is held in memory and never the text or a
+/// `ToonValue` copy.
+pub fn encode_to_stream<W: io::Write>(
+    input: &Zval,
+    options: &EncodeOptions,
+    encoder: &mut toon::StreamEncoder<W>,
+) -> PhpResult<()> {
+    let plan = Plan::new(input, options)?;
+    encoder
+        .write_with(|mut out| plan.write(input, &options.format, &mut out))
+        .map_err(|e| PhpException::default(e.to_string()))
+}
+
+/// A value that is not a scalar, in the order the values are walked.
+enum Slot<'z> {
+    /// An array.
+    Table {
+        ht: &'z ZendHashTable,
+        is_map: bool,
+        /// Index of the first slot after the ones of its children.
+        end: usize,
+    },
+    /// Any other object or value, converted as usual. Boxed to keep the
+    /// slots of large arrays small.
+    Converted(Box<ToonValue>),
+}
+
+#[derive(Default)]
+struct Plan<'z> {
+    slots: Vec<Slot<'z>>,
+    /// Rough size of the output, to allocate it once.
+    size_hint: usize,
+}
+
+impl<'z> Plan<'z> {
+    /// Checks all of `input`, recording the values substituted on the way.
+    fn new(input: &'z Zval, options: &EncodeOptions) -> PhpResult<Self> {
+        let mut ctx = EncodeContext::new(options);
+        let mut plan = Self::default();
+        let result = plan.classify(input, 0, &mut ctx);
+        LAST_SUBSTITUTIONS.set(ctx.substitutions);
+        result.map(|_| plan)
+    }
+
+    /// Writes `input`, the value the plan was made for.
+    fn write<W: Write>(
+        &self,
+        input: &'z Zval,
+        opts: &toon::EncodeOptions,
+        out: &mut W,
+    ) -> fmt::Result {
+        let mut writer = Writer {
+            slots: &self.slots,
+            next: 0,
+            opts,
+        };
+        let root = writer.take(input);
+        writer.value(root, 0, out, 0)
+    }
+
+    /// Checks `zval` like `zval_to_toon_value_impl` and records its slot,
+    /// returning whether it encodes as a map.
+    fn classify(
+        &mut self,
+        zval: &'z Zval,
+        depth: usize,
+        ctx: &mut EncodeContext,
+    ) -> PhpResult<bool> {
+        if depth > ctx.options.format.max_depth {
+            return Err(PhpException::default(
+                "Recursion depth limit exceeded".to_string(),
+            ));
+        }
+
+        let zval = zval.dereference();
+        if let Some(s) = zval.str() {
+            self.size_hint += s.len() + 2;
+            return Ok(false);
+        }
+        if is_scalar(zval) {
+            self.size_hint += 8;
+            return Ok(false);
+        }
+        if let Some(ht) = zval.array() {
+            return ctx.enter(ptr::from_ref(ht).cast(), |ctx| {
+                self.classify_table(ht, depth, ctx)
+            });
+        }
+        let val = zval_to_toon_value_impl(zval, depth, ctx)?;
+        let is_map = matches!(val, ToonValue::Map(_));
+        self.size_hint += 16;
+        self.slots.push(Slot::Converted(Box::new(val)));
+        Ok(is_map)
+    }
+
+    /// Same rules as `hashtable_to_toon_value`: a list needs sequential keys
+    /// and no child encoding as a map.
+    fn classify_table(
+        &mut self,
+        ht: &'z ZendHashTable,
+        depth: usize,
+        ctx: &mut EncodeContext,
+    ) -> PhpResult<bool> {
+        let index = self.slots.len();
+        self.slots.push(Slot::Table {
+            ht,
+            is_map: false,
+            end: 0,
+        });
+
+        let mut is_list = true;
+        for (i, (key, value)) in entries(ht).enumerate() {
+            is_list &= key.long() == Some(i as i64);
+            self.size_hint += key.str().map_or(4, str::len) + depth * 2 + 4;
+
+            // Scalars never need their key on the path, for error reporting
+            let tracked = !is_scalar(value);
+            if tracked {
+                ctx.path.push(key_string(&key));
+            }
+            let child_is_map = self.classify(value, depth + 1, ctx);
+            if tracked {
+                ctx.path.pop();
+            }
+            is_list &= !child_is_map?;
+        }
+
+        let end = self.slots.len();
+        if let Slot::Table {
+            is_map, end: last, ..
+        } = &mut self.slots[index]
+        {
+            *is_map = !is_list;
+            *last = end;
+        }
+        Ok(!is_list)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Value<'s, 'z> {
+    Scalar(&'z Zval),
+    Slot(&'s Slot<'z>),
+}
+
+/// Writes the planned values, mirroring `encode_recursive_impl`,
+/// `encode_entry` and `value_to_string` in `toon`.
+struct Writer<'s, 'z> {
+    slots: &'s [Slot<'z>],
+    /// Slot of the next value that is not a scalar.
+    next: usize,
+    opts: &'s toon::EncodeOptions,
+}
+
+impl<'s, 'z> Writer<'s, 'z> {
+    fn take(&mut self, zval: &'z Zval) -> Value<'s, 'z> {
+        if is_scalar(zval) {
+            return Value::Scalar(zval.dereference());
+        }
+        let slots = self.slots;
+        self.next += 1;
+        Value::Slot(&slots[self.next - 1])
+    }
+
+    /// Moves past the children of a value that is not written.
+    fn skip(&mut self, value: Value<'s, 'z>) {
+        if let Value::Slot(Slot::Table { end, .. }) = value {
+            self.next = *end;
+        }
+    }
+
+    fn value<W: Write>(
+        &mut self,
+        value: Value<'s, 'z>,
+        indent: usize,
+        out: &mut W,
+        depth: usize,
+    ) -> fmt::Result {
+        if depth > self.opts.depth_limit() {
+            self.skip(value);
+            return out.write_str("[MaxDepthExceeded]");
+        }
+
+        match value {
+            Value::Slot(Slot::Converted(val)) => {
+                toon::encode_recursive_impl(val, indent, out, depth, self.opts)
+            }
+            Value::Slot(&Slot::Table {
+                ht, is_map: true, ..
+            }) => {
+                for (key, child) in entries(ht) {
+                    let child = self.take(child);
+                    self.entry(&key, child, indent, out, depth)?;
+                }
+                Ok(())
+            }
+            Value::Slot(&Slot::Table { ht, .. }) => {
+                for (i, (_, child)) in entries(ht).enumerate() {
+                    if i > 0 {
+                        out.write_str(self.opts.delimiter.separator())?;
+                    }
+                    let child = self.take(child);
+                    self.inline(child, out)?;
+                }
+                out.write_char('\n')
+            }
+            Value::Scalar(zval) => {
+                write_scalar(out, zval, self.opts.delimiter)?;
+                out.write_char('\n')
+            }
+        }
+    }
+
+    fn entry<W: Write>(
+        &mut self,
+        key: &Zval,
+        value: Value<'s, 'z>,
+        indent: usize,
+        out: &mut W,
+        depth: usize,
+    ) -> fmt::Result {
+        match value {
+            Value::Slot(Slot::Converted(val)) => {
+                toon::encode_entry(&key_string(key), val, indent, out, depth, self.opts)
+            }
+            Value::Slot(Slot::Table { is_map: true, .. }) => {
+                toon::write_indent(out, indent)?;
+                write_key(out, key)?;
+                out.write_str(":\n")?;
+                self.value(value, indent + self.opts.indent, out, depth + 1)
+            }
+            _ => {
+                toon::write_indent(out, indent)?;
+                write_key(out, key)?;
+                out.write_str(": ")?;
+                self.value(value, 0, out, depth + 1)
+            }
+        }
+    }
+
+    fn inline<W: Write>(&mut self, value: Value<'s, 'z>, out: &mut W) -> fmt::Result {
+        match value {
+            Value::Scalar(zval) => write_scalar(out, zval, self.opts.delimiter),
+            Value::Slot(Slot::Converted(val)) => {
+                out.write_str(&toon::value_to_string(val, self.opts.delimiter))
+            }
+            Value::Slot(Slot::Table { is_map: true, .. }) => {
+                self.skip(value);
+                out.write_str("[Object]")
+            }
+            Value::Slot(&Slot::Table { ht, .. }) => {
+                out.write_char('[')?;
+                for (i, (_, child)) in entries(ht).enumerate() {
+                    if i > 0 {
+                        out.write_str(self.opts.delimiter.separator())?;
+                    }
+                    let child = self.take(child);
+                    self.inline(child, out)?;
+                }
+                out.write_char(']')
+            }
+        }
+    }
+}
+
+/// Keys and values of `ht`, with the keys as zvals.
+fn entries(ht: &ZendHashTable) -> impl Iterator<Item = (Zval, &Zval)> {
+    let mut iter = ht.iter();
+    iter::from_fn(move || iter.next_zval())
+}
+
+fn key_string(key: &Zval) -> String {
+    match key.long() {
+        Some(idx) => idx.to_string(),
+        None => key.string().unwrap_or_default(),
+    }
+}
+
+fn write_key<W: Write>(out: &mut W, key: &Zval) -> fmt::Result {
+    match key.long() {
+        Some(idx) => write!(out, "{idx}"),
+        None => out.write_str(key.str().unwrap_or_default()),
+    }
+}
+
+fn write_scalar<W: Write>(out: &mut W, zval: &Zval, delimiter: Delimiter) -> fmt::Result {
+    if let Some(b) = zval.bool() {
+        write!(out, "{b}")
+    } else if let Some(i) = zval.long() {
+        write!(out, "{i}")
+    } else if let Some(f) = zval.double() {
+        write!(out, "{f}")
+    } else if zval.is_string() {
+        toon::write_string(out, zval.str().unwrap_or_default(), delimiter)
+    } else {
+        out.write_str("null")
+    }
+}
@@ -0,0 +1,57 @@
+use ext_php_rs::builders::ModuleBuilder;
+use ext_php_rs::exception::PhpException;
+use ext_php_rs::zend::ce;
+
+use crate::toon::ToonError;
+
+/// Codes of the exceptions thrown for [`ToonError`]s, registered as PHP
+/// constants. Codes are never reused, so new variants get new numbers.
+const ERROR_CODES: [(&str, i32); 12] = [
+    ("TOON_ERROR_UNEXPECTED_INDENT", 1),
+    ("TOON_ERROR_MISSING_KEY", 2),
+    ("TOON_ERROR_COUNT_MISMATCH", 3),
+    ("TOON_ERROR_INVALID_ESCAPE", 4),
+    ("TOON_ERROR_UNTERMINATED_STRING", 5),
+    ("TOON_ERROR_DEPTH_EXCEEDED", 6),
+    ("TOON_ERROR_DUPLICATE_KEY", 7),
+    ("TOON_ERROR_INVALID_PATH", 8),
+    ("TOON_ERROR_TABLE_NOT_FOUND", 9),
+    ("TOON_ERROR_IO", 10),
+    ("TOON_ERROR_SINK", 11),
+    ("TOON_ERROR_TRAILING_CONTENT", 12),
+];
+
+pub fn register_constants(module: ModuleBuilder) -> ModuleBuilder {
+    ERROR_CODES
+        .into_iter()
+        .fold(module, |module, (name, code)| {
+            module.constant((name, code, &[]))
+        })
+}
+
+fn code(err: &ToonError) -> i32 {
+    let name = match err {
+        ToonError::UnexpectedIndent { .. } => "TOON_ERROR_UNEXPECTED_INDENT",
+        ToonError::MissingKey { .. } => "TOON_ERROR_MISSING_KEY",
+        ToonError::CountMismatch { .. } => "TOON_ERROR_COUNT_MISMATCH",
+        ToonError::InvalidEscape { .. } => "TOON_ERROR_INVALID_ESCAPE",
+        ToonError::UnterminatedString { .. } => "TOON_ERROR_UNTERMINATED_STRING",
+        ToonError::DepthExceeded { .. } => "TOON_ERROR_DEPTH_EXCEEDED",
+        ToonError::DuplicateKey { .. } => "TOON_ERROR_DUPLICATE_KEY",
+        ToonError::InvalidPath { .. } => "TOON_ERROR_INVALID_PATH",
+        ToonError::TableNotFound { .. } => "TOON_ERROR_TABLE_NOT_FOUND",
+        ToonError::Io { .. } => "TOON_ERROR_IO",
+        ToonError::Sink { .. } => "TOON_ERROR_SINK",
+        ToonError::TrailingContent { .. } => "TOON_ERROR_TRAILING_CONTENT",
+    };
+    ERROR_CODES
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map_or(0, |(_, code)| *code)
+}
+
+/// The exception thrown for a parser error: its message, with the code of
+/// its variant.
+pub fn toon_exception(err: ToonError) -> PhpException {
+    PhpException::new(err.to_string(), code(&err), ce::exception())
+}
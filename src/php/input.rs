@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read};
+use std::ops::Deref;
+
+use ext_php_rs::error::Error;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendCallable, ZendStr, Zval};
+
+use super::php_error;
+
+/// Files at least this large are memory-mapped instead of read.
+#[cfg(unix)]
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Bytes requested per `fread()` call when reading a PHP stream.
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Raw bytes of a TOON document, either owned or mapped from a file.
+pub enum Contents {
+    Owned(Vec<u8>),
+    #[cfg(unix)]
+    Mapped(Mmap),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(bytes) => bytes,
+            #[cfg(unix)]
+            Self::Mapped(map) => map,
+        }
+    }
+}
+
+/// Reads a local file, mapping it into memory when it is large.
+pub fn read_file(path: &str) -> io::Result<Contents> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    #[cfg(unix)]
+    if len >= MMAP_THRESHOLD {
+        return Mmap::new(&file, len).map(Contents::Mapped);
+    }
+
+    let mut bytes = Vec::with_capacity(usize::try_from(len).unwrap_or_default());
+    file.read_to_end(&mut bytes)?;
+    Ok(Contents::Owned(bytes))
+}
+
+/// Drains a PHP stream resource through `fread()`, so any stream wrapper or
+/// filter works. Stops with an error once more than `limit` bytes have been
+/// read (`0` means unlimited).
+pub fn read_stream(stream: &Zval, limit: usize) -> PhpResult<Contents> {
+    let fread = ZendCallable::try_from_name("fread")?;
+    let chunk_size = STREAM_CHUNK_SIZE as i64;
+    let mut bytes = Vec::new();
+    loop {
+        let chunk = fread
+            .try_call(vec![stream, &chunk_size])
+            .map_err(php_error)?;
+        let chunk = chunk
+            .zend_str()
+            .ok_or_else(|| PhpException::default("Could not read from stream".to_string()))?;
+        if chunk.is_empty() {
+            break;
+        }
+        bytes.extend_from_slice(chunk.as_bytes());
+        if limit > 0 && bytes.len() > limit {
+            return Err(PhpException::default(format!(
+                "Input exceeds the maximum size of {limit} bytes"
+            )));
+        }
+    }
+    Ok(Contents::Owned(bytes))
+}
+
+/// `io::Read` over a PHP stream resource, for consumers that outlive the
+/// current call (it keeps its own reference to the stream).
+pub struct StreamReader {
+    stream: Zval,
+    fread: ZendCallable<'static>,
+}
+
+impl StreamReader {
+    pub fn new(stream: &Zval) -> PhpResult<Self> {
+        Ok(Self {
+            stream: stream.shallow_clone(),
+            fread: ZendCallable::try_from_name("fread")?,
+        })
+    }
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = i64::try_from(buf.len()).unwrap_or(i64::MAX);
+        let chunk = self
+            .fread
+            .try_call(vec![&self.stream, &len])
+            .map_err(stream_error)?;
+        let chunk = chunk
+            .zend_str()
+            .ok_or_else(|| io::Error::other("Could not read from stream"))?;
+        let bytes = chunk.as_bytes();
+        buf[..bytes.len()].copy_from_slice(bytes);
+        Ok(bytes.len())
+    }
+}
+
+/// `io::BufRead` over a PHP string, read in place. It holds a reference to
+/// the string, which PHP never modifies while it is shared, so the bytes are
+/// not copied.
+pub struct StringReader {
+    string: Zval,
+    pos: usize,
+}
+
+impl StringReader {
+    /// `None` unless `string` holds a string.
+    pub fn new(string: &Zval) -> Option<Self> {
+        string.zend_str().map(|_| Self {
+            string: string.shallow_clone(),
+            pos: 0,
+        })
+    }
+
+    fn rest(&self) -> &[u8] {
+        let bytes = self.string.zend_str().map_or(&[][..], ZendStr::as_bytes);
+        &bytes[self.pos..]
+    }
+}
+
+impl Read for StringReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.rest().read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl BufRead for StringReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.rest())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+/// Turns a failed `fread()` call into an `io::Error`, keeping the message of
+/// an exception thrown by a userland stream wrapper.
+fn stream_error(err: Error) -> io::Error {
+    let message = match &err {
+        Error::Exception(exception) => exception
+            .try_call_method("getMessage", vec![])
+            .ok()
+            .and_then(|message| message.string()),
+        _ => None,
+    };
+    io::Error::other(message.unwrap_or_else(|| err.to_string()))
+}
+
+/// A read-only private mapping of a whole file.
+#[cfg(unix)]
+pub struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mmap {
+    fn new(file: &File, len: u64) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        // SAFETY: `len` is non-zero (at least MMAP_THRESHOLD) and the
+        // descriptor stays open for the duration of the call; the mapping
+        // outlives it.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+}
+
+#[cfg(unix)]
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`.
+        unsafe { std::slice::from_raw_parts(self.ptr.cast::<u8>(), self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: `ptr`/`len` describe a mapping created by `Mmap::new`.
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use ext_php_rs::ffi::zend_ini_entry;
+use ext_php_rs::flags::IniEntryPermission;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendHashTable, Zval};
+use ext_php_rs::zend::{ExecutorGlobals, IniEntryDef};
+
+use crate::toon::{self, Delimiter, DuplicateKeys};
+
+/// `DateTimeInterface::format()` pattern used when none is configured
+/// (the `DATE_ATOM` constant).
+pub const DEFAULT_DATE_FORMAT: &str = "Y-m-d\\TH:i:sP";
+
+/// Nesting limit used when neither `toon.max_depth` nor the `max_depth`
+/// option says otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 60;
+
+/// INI directives registered by the extension, with their defaults. They
+/// only seed the options of a call; anything passed explicitly wins.
+pub const INI_ENTRIES: [(&str, &str); 5] = [
+    ("toon.max_depth", "60"),
+    ("toon.indent", "2"),
+    ("toon.delimiter", ","),
+    ("toon.strict", "0"),
+    ("toon.max_input_size", "0"),
+];
+
+pub fn register_ini_entries(module_number: i32) {
+    let entries = INI_ENTRIES
+        .iter()
+        .map(|(name, value)| {
+            IniEntryDef::new(
+                name.to_string(),
+                value.to_string(),
+                &IniEntryPermission::All,
+            )
+        })
+        .collect();
+    IniEntryDef::register(entries, module_number);
+}
+
+/// What to do with values TOON cannot represent (resources, closures).
+#[derive(Debug, Default)]
+pub enum UnsupportedPolicy {
+    #[default]
+    Throw,
+    /// Encode `null` in their place.
+    Null,
+    /// Encode whatever the callable returns for `($value, $path)`.
+    Convert(Zval),
+}
+
+impl Clone for UnsupportedPolicy {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Throw => Self::Throw,
+            Self::Null => Self::Null,
+            Self::Convert(callable) => Self::Convert(callable.shallow_clone()),
+        }
+    }
+}
+
+/// What to do with rows of a `Traversable` whose fields differ from those
+/// of its first row.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RowMismatch {
+    #[default]
+    Error,
+    /// Leave the row out.
+    Skip,
+    /// Use `null` for missing fields and drop extra ones.
+    Fill,
+}
+
+/// Options accepted by `toon_encode()`.
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// Output settings; `max_depth` also limits the PHP values converted for
+    /// the output.
+    pub format: toon::EncodeOptions,
+    /// Format passed to `DateTimeInterface::format()`.
+    pub date_format: String,
+    pub unsupported: UnsupportedPolicy,
+    pub row_mismatch: RowMismatch,
+    /// Convert the whole value to a `ToonValue` and encode that, as
+    /// `toon_encode()` did before writing straight from the PHP values.
+    /// Left out of the documented options; it is the baseline of the
+    /// `*EncodeRustTree` benchmarks.
+    pub tree: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            format: toon::EncodeOptions {
+                max_depth: DEFAULT_MAX_DEPTH,
+                ..toon::EncodeOptions::default()
+            },
+            date_format: DEFAULT_DATE_FORMAT.to_string(),
+            unsupported: UnsupportedPolicy::default(),
+            row_mismatch: RowMismatch::default(),
+            tree: false,
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// Defaults taken from the `toon.*` INI settings.
+    pub fn from_ini() -> Self {
+        let defaults = Self::default();
+        Self {
+            format: toon::EncodeOptions {
+                indent: ini_usize("toon.indent", defaults.format.indent),
+                delimiter: ini_delimiter(defaults.format.delimiter),
+                max_depth: ini_usize("toon.max_depth", defaults.format.max_depth)
+                    .min(toon::MAX_DEPTH_LIMIT),
+                ..defaults.format
+            },
+            ..defaults
+        }
+    }
+
+    pub fn from_php(options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        Self::from_ini().merge("toon_encode()", options)
+    }
+
+    /// Applies an options array on top of `self`; `caller` names the
+    /// function or class in error messages.
+    pub fn merge(mut self, caller: &str, options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        for (key, value) in options.into_iter().flat_map(|ht| ht.iter()) {
+            self.set(caller, &key.to_string(), value)?;
+        }
+        Ok(self)
+    }
+
+    pub fn set(&mut self, caller: &str, name: &str, value: &Zval) -> PhpResult<()> {
+        match name {
+            "max_depth" => self.format.max_depth = depth_value(value)?,
+            "indent" => self.format.indent = usize_value("indent", value)?,
+            "delimiter" => self.format.delimiter = delimiter_value(value)?,
+            "lengthless" => self.format.lengthless = bool_value("lengthless", value)?,
+            "date_format" => self.date_format = string_value("date_format", value)?,
+            "unsupported" => self.unsupported = unsupported_policy(value)?,
+            "row_mismatch" => self.row_mismatch = row_mismatch(value)?,
+            "tree" => self.tree = bool_value("tree", value)?,
+            other => return Err(unknown_option(caller, other)),
+        }
+        Ok(())
+    }
+}
+
+/// Which decoded strings are turned into `DateTimeImmutable` objects.
+#[derive(Debug, Clone, Default)]
+pub enum DateDecoding {
+    #[default]
+    Off,
+    Everywhere,
+    /// Only strings at or below one of these dotted paths.
+    Paths(Vec<String>),
+}
+
+/// Options accepted by `toon_decode()`.
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// Parser settings; `max_depth` also limits the PHP value built from the
+    /// parsed document.
+    pub parse: toon::ParseOptions,
+    /// Largest input accepted, in bytes; `0` means unlimited.
+    pub max_input_size: usize,
+    /// Decode maps into `stdClass` objects instead of arrays.
+    pub objects: bool,
+    /// Enum class names keyed by dotted path (`order.status`) or bare field
+    /// name (`status`).
+    pub enums: Vec<(String, String)>,
+    pub dates: DateDecoding,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            parse: toon::ParseOptions {
+                max_depth: DEFAULT_MAX_DEPTH,
+                ..toon::ParseOptions::default()
+            },
+            max_input_size: 0,
+            objects: false,
+            enums: Vec::new(),
+            dates: DateDecoding::default(),
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// Defaults taken from the `toon.*` INI settings.
+    pub fn from_ini() -> Self {
+        let defaults = Self::default();
+        Self {
+            parse: toon::ParseOptions {
+                delimiter: ini_delimiter(defaults.parse.delimiter),
+                strict: ini_bool("toon.strict", defaults.parse.strict),
+                max_depth: ini_usize("toon.max_depth", defaults.parse.max_depth)
+                    .min(toon::MAX_DEPTH_LIMIT),
+                ..defaults.parse
+            },
+            max_input_size: ini_usize("toon.max_input_size", defaults.max_input_size),
+            ..defaults
+        }
+    }
+
+    pub fn from_php(options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        Self::from_ini().merge("toon_decode()", options)
+    }
+
+    /// Applies an options array on top of `self`; `caller` names the
+    /// function or class in error messages.
+    pub fn merge(mut self, caller: &str, options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        for (key, value) in options.into_iter().flat_map(|ht| ht.iter()) {
+            self.set(caller, &key.to_string(), value)?;
+        }
+        Ok(self)
+    }
+
+    pub fn set(&mut self, caller: &str, name: &str, value: &Zval) -> PhpResult<()> {
+        match name {
+            "max_depth" => self.parse.max_depth = depth_value(value)?,
+            "delimiter" => self.parse.delimiter = delimiter_value(value)?,
+            "strict" => self.parse.strict = bool_value("strict", value)?,
+            "duplicate_keys" => self.parse.duplicate_keys = duplicate_keys(value)?,
+            "max_input_size" => self.max_input_size = usize_value("max_input_size", value)?,
+            "objects" => self.objects = bool_value("objects", value)?,
+            "enums" => self.enums = string_pairs("enums", value)?,
+            "dates" => self.dates = date_decoding(value)?,
+            other => return Err(unknown_option(caller, other)),
+        }
+        Ok(())
+    }
+
+    /// Whether the decoder has to keep track of the current key path.
+    pub fn tracks_paths(&self) -> bool {
+        !self.enums.is_empty() || matches!(self.dates, DateDecoding::Paths(_))
+    }
+
+    /// Whether an ISO 8601 string found at `path` should become a date.
+    pub fn decodes_date_at(&self, path: &[String]) -> bool {
+        match &self.dates {
+            DateDecoding::Off => false,
+            DateDecoding::Everywhere => true,
+            DateDecoding::Paths(targets) => {
+                targets.iter().any(|target| path_starts_with(path, target))
+            }
+        }
+    }
+
+    /// Returns the enum class configured for `path`, matching either the full
+    /// dotted path or its last segment.
+    pub fn enum_for(&self, path: &[String]) -> Option<&str> {
+        let field = path.last()?;
+        self.enums
+            .iter()
+            .find(|(target, _)| target == field || path_matches(target, path))
+            .map(|(_, class)| class.as_str())
+    }
+}
+
+fn path_matches(target: &str, path: &[String]) -> bool {
+    let mut segments = target.split('.');
+    for part in path {
+        if segments.next() != Some(part.as_str()) {
+            return false;
+        }
+    }
+    segments.next().is_none()
+}
+
+fn path_starts_with(path: &[String], target: &str) -> bool {
+    let mut parts = path.iter();
+    target
+        .split('.')
+        .all(|segment| parts.next().is_some_and(|part| part == segment))
+}
+
+fn date_decoding(value: &Zval) -> PhpResult<DateDecoding> {
+    if let Some(enabled) = value.bool() {
+        return Ok(if enabled {
+            DateDecoding::Everywhere
+        } else {
+            DateDecoding::Off
+        });
+    }
+
+    let ht = value.array().ok_or_else(|| {
+        PhpException::default("Option 'dates' must be a bool or a list of paths".to_string())
+    })?;
+    let mut paths = Vec::with_capacity(ht.len());
+    for (_, path) in ht.iter() {
+        paths.push(string_value("dates", path)?);
+    }
+    Ok(DateDecoding::Paths(paths))
+}
+
+fn unsupported_policy(value: &Zval) -> PhpResult<UnsupportedPolicy> {
+    match value.str() {
+        Some("throw") => Ok(UnsupportedPolicy::Throw),
+        Some("null") => Ok(UnsupportedPolicy::Null),
+        _ if value.is_callable() => Ok(UnsupportedPolicy::Convert(value.shallow_clone())),
+        _ => Err(PhpException::default(
+            "Option 'unsupported' must be 'throw', 'null' or a callable".to_string(),
+        )),
+    }
+}
+
+fn row_mismatch(value: &Zval) -> PhpResult<RowMismatch> {
+    match value.str() {
+        Some("error") => Ok(RowMismatch::Error),
+        Some("skip") => Ok(RowMismatch::Skip),
+        Some("fill") => Ok(RowMismatch::Fill),
+        _ => Err(PhpException::default(
+            "Option 'row_mismatch' must be 'error', 'skip' or 'fill'".to_string(),
+        )),
+    }
+}
+
+fn duplicate_keys(value: &Zval) -> PhpResult<DuplicateKeys> {
+    value
+        .str()
+        .and_then(DuplicateKeys::from_name)
+        .ok_or_else(|| {
+            PhpException::default(
+                "Option 'duplicate_keys' must be 'error', 'first' or 'last'".to_string(),
+            )
+        })
+}
+
+fn usize_value(option: &str, value: &Zval) -> PhpResult<usize> {
+    value
+        .long()
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or_else(|| {
+            PhpException::default(format!("Option '{option}' must be a non-negative integer"))
+        })
+}
+
+/// A `max_depth`, capped at [`toon::MAX_DEPTH_LIMIT`] since encoding and
+/// decoding recurse once per level.
+fn depth_value(value: &Zval) -> PhpResult<usize> {
+    usize_value("max_depth", value).map(|depth| depth.min(toon::MAX_DEPTH_LIMIT))
+}
+
+fn bool_value(option: &str, value: &Zval) -> PhpResult<bool> {
+    value
+        .bool()
+        .ok_or_else(|| PhpException::default(format!("Option '{option}' must be a bool")))
+}
+
+fn delimiter_value(value: &Zval) -> PhpResult<Delimiter> {
+    value.str().and_then(Delimiter::from_name).ok_or_else(|| {
+        PhpException::default(
+            "Option 'delimiter' must be ',', '\\t' or '|' (or 'comma', 'tab', 'pipe')".to_string(),
+        )
+    })
+}
+
+fn string_value(option: &str, value: &Zval) -> PhpResult<String> {
+    value
+        .string()
+        .ok_or_else(|| PhpException::default(format!("Option '{option}' must be a string")))
+}
+
+fn string_pairs(option: &str, value: &Zval) -> PhpResult<Vec<(String, String)>> {
+    let ht = value
+        .array()
+        .ok_or_else(|| PhpException::default(format!("Option '{option}' must be an array")))?;
+
+    let mut pairs = Vec::with_capacity(ht.len());
+    for (key, class) in ht.iter() {
+        let class = class.string().ok_or_else(|| {
+            PhpException::default(format!("Option '{option}' must map keys to class names"))
+        })?;
+        pairs.push((key.to_string(), class));
+    }
+    Ok(pairs)
+}
+
+fn unknown_option(caller: &str, name: &str) -> PhpException {
+    PhpException::default(format!("Unknown {caller} option '{name}'"))
+}
+
+/// Current value of an INI directive, honouring per-directory and runtime
+/// overrides.
+pub fn ini_value(name: &str) -> Option<String> {
+    let globals = ExecutorGlobals::get();
+    let directives = unsafe { globals.ini_directives.as_ref() }?;
+    let entry = unsafe { directives.get(name)?.ptr::<zend_ini_entry>()?.as_ref() }?;
+    let value = unsafe { entry.value.as_ref() }?;
+    value.as_str().ok().map(str::to_owned)
+}
+
+// Malformed INI values fall back to the built-in default, like PHP's own
+// numeric directives do.
+
+fn ini_usize(name: &str, default: usize) -> usize {
+    ini_value(name)
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+fn ini_bool(name: &str, default: bool) -> bool {
+    match ini_value(name) {
+        Some(value) => matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "1" | "on" | "yes" | "true"
+        ),
+        None => default,
+    }
+}
+
+fn ini_delimiter(default: Delimiter) -> Delimiter {
+    ini_value("toon.delimiter")
+        .and_then(|value| Delimiter::from_name(&value))
+        .unwrap_or(default)
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+use std::io;
+use std::ptr::{self, addr_of_mut};
+
+use ext_php_rs::boxed::ZBox;
+use ext_php_rs::convert::IntoZval;
+use ext_php_rs::error::Result;
+use ext_php_rs::ffi::zend_string;
+use ext_php_rs::flags::DataType;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendCallable, ZendStr, Zval};
+
+use super::php_error;
+
+/// Bytes buffered before they are handed to `fwrite()`.
+pub const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// `io::Write` over a PHP stream resource, going through `fwrite()` so any
+/// stream wrapper or filter works.
+pub struct StreamWriter<'a> {
+    stream: &'a Zval,
+    fwrite: ZendCallable<'static>,
+    /// Total bytes accepted by the stream.
+    pub written: usize,
+    /// Exception thrown by PHP code during a write (e.g. a userland stream
+    /// wrapper), kept so it can be rethrown as is.
+    pub exception: Option<PhpException>,
+}
+
+impl<'a> StreamWriter<'a> {
+    pub fn new(stream: &'a Zval) -> PhpResult<Self> {
+        Ok(Self {
+            stream,
+            fwrite: ZendCallable::try_from_name("fwrite")?,
+            written: 0,
+            exception: None,
+        })
+    }
+}
+
+impl io::Write for StreamWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A chunk may end inside a multi-byte character, so pass raw bytes
+        let mut chunk = Zval::new();
+        chunk.set_zend_string(ZendStr::new(buf, false));
+        let result = self
+            .fwrite
+            .try_call(vec![self.stream, &chunk])
+            .map_err(|e| {
+                self.exception = Some(php_error(e));
+                io::Error::other("fwrite() threw an exception")
+            })?;
+        match result.long() {
+            Some(n) if n > 0 => {
+                let n = n as usize;
+                self.written += n;
+                Ok(n)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "Could not write to stream",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `smart_str` from `zend_smart_str_public.h`.
+#[repr(C)]
+struct SmartStr {
+    s: *mut zend_string,
+    /// Bytes the string can hold.
+    a: usize,
+}
+
+// `smart_str_erealloc()` grows `dest` to hold `len` bytes in total,
+// allocating its string on first use. It is `ZEND_FASTCALL`, which only
+// differs from the C calling convention on 32-bit x86
+#[cfg(target_arch = "x86")]
+extern "fastcall" {
+    fn smart_str_erealloc(dest: *mut SmartStr, len: usize);
+}
+
+#[cfg(not(target_arch = "x86"))]
+extern "C" {
+    fn smart_str_erealloc(dest: *mut SmartStr, len: usize);
+}
+
+/// Output written straight into a PHP string, which is handed over to PHP
+/// as is instead of being copied out of a Rust `String`.
+pub struct ZendStringWriter {
+    buf: SmartStr,
+}
+
+impl ZendStringWriter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut writer = Self {
+            buf: SmartStr {
+                s: ptr::null_mut(),
+                a: 0,
+            },
+        };
+        writer.reserve(capacity);
+        writer
+    }
+
+    fn len(&self) -> usize {
+        // SAFETY: a non-null `s` is a string allocated by `smart_str_erealloc`
+        unsafe { self.buf.s.as_ref() }.map_or(0, |s| s.len)
+    }
+
+    /// Makes room for `additional` more bytes, at least doubling the
+    /// capacity when it has to grow so appends stay amortized O(1).
+    fn reserve(&mut self, additional: usize) {
+        let len = self.len() + additional;
+        if self.buf.s.is_null() || len >= self.buf.a {
+            let len = len.max(self.buf.a.saturating_mul(2));
+            // SAFETY: `buf` is either empty or was filled by this function
+            unsafe { smart_str_erealloc(addr_of_mut!(self.buf), len) };
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        // SAFETY: `reserve` ran in the constructor, so `s` is allocated;
+        // `val` is the start of its `a + 1` bytes of text
+        unsafe { addr_of_mut!((*self.buf.s).val).cast::<u8>() }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: the first `len` bytes of `val` have been written
+        unsafe {
+            let val = addr_of_mut!((*self.buf.s).val).cast::<u8>();
+            std::slice::from_raw_parts(val, self.len())
+        }
+    }
+
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        self.reserve(bytes.len());
+        let len = self.len();
+        // SAFETY: `reserve` left room for `bytes` after the first `len` bytes
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), self.as_mut_ptr().add(len), bytes.len());
+            (*self.buf.s).len = len + bytes.len();
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            // SAFETY: `s` is allocated and only shrinks here
+            unsafe { (*self.buf.s).len = len };
+        }
+    }
+
+    /// Drops trailing whitespace, as `str::trim_end` would.
+    pub fn trim_end(&mut self) {
+        // Only `str`s are ever written, so the bytes are valid UTF-8
+        let len = match std::str::from_utf8(self.as_bytes()) {
+            Ok(text) => text.trim_end().len(),
+            Err(_) => return,
+        };
+        self.truncate(len);
+    }
+
+    /// Replaces the first `reserved` bytes, written as placeholders, with
+    /// `prefix`, moving the rest of the text back if `prefix` is shorter.
+    pub fn replace_start(&mut self, reserved: usize, prefix: &[u8]) {
+        let len = self.len();
+        assert!(prefix.len() <= reserved && reserved <= len);
+        let start = self.as_mut_ptr();
+        // SAFETY: both ranges lie within the first `len` bytes, and
+        // `ptr::copy` allows them to overlap
+        unsafe {
+            ptr::copy(start.add(reserved), start.add(prefix.len()), len - reserved);
+            ptr::copy_nonoverlapping(prefix.as_ptr(), start, prefix.len());
+        }
+        self.truncate(len - reserved + prefix.len());
+    }
+
+    /// Terminates the string and hands it over.
+    pub fn finish(mut self) -> ZendString {
+        let len = self.len();
+        // SAFETY: `smart_str_erealloc` keeps one byte past the capacity for
+        // the terminator
+        unsafe { *self.as_mut_ptr().add(len) = 0 };
+        let s = std::mem::replace(&mut self.buf.s, ptr::null_mut());
+        // SAFETY: `s` is a valid, uniquely owned string
+        ZendString(unsafe { ZBox::from_raw(s) })
+    }
+}
+
+impl Drop for ZendStringWriter {
+    fn drop(&mut self) {
+        if !self.buf.s.is_null() {
+            // SAFETY: `s` is a valid, uniquely owned string
+            drop(unsafe { ZBox::<ZendStr>::from_raw(self.buf.s) });
+        }
+    }
+}
+
+impl fmt::Write for ZendStringWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl io::Write for ZendStringWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.push_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A PHP string returned to PHP without being copied.
+pub struct ZendString(ZBox<ZendStr>);
+
+impl From<String> for ZendString {
+    fn from(s: String) -> Self {
+        Self(ZendStr::new(&s, false))
+    }
+}
+
+impl IntoZval for ZendString {
+    const TYPE: DataType = DataType::String;
+    const NULLABLE: bool = false;
+
+    fn set_zval(self, zv: &mut Zval, _persistent: bool) -> Result<()> {
+        zv.set_zend_string(self.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use std::io;
+
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::ZendObject;
+use ext_php_rs::zend::ClassEntry;
+
+use super::options::EncodeOptions;
+use super::output::{ZendString, ZendStringWriter};
+use super::{is_scalar, php_error, zval_to_toon_value_impl, EncodeContext, LAST_SUBSTITUTIONS};
+use crate::toon::{StreamEncoder, ToonValue};
+
+/// `PDO::FETCH_NUM`: rows come back as lists in column order.
+const FETCH_NUM: i64 = 3;
+
+/// Encodes the remaining rows of an executed `PDOStatement` as a root
+/// table, fetching them one at a time.
+pub fn encode_statement(stmt: &ZendObject, options: &EncodeOptions) -> PhpResult<ZendString> {
+    if !ClassEntry::try_find("PDOStatement").is_some_and(|ce| stmt.instance_of(ce)) {
+        return Err(PhpException::default(
+            "toon_encode_pdo() expects a PDOStatement".to_string(),
+        ));
+    }
+    let columns = column_names(stmt)?;
+    if columns.is_empty() {
+        return Err(PhpException::default(
+            "Statement has no result set; execute a query first".to_string(),
+        ));
+    }
+    let fields: Vec<&str> = columns.iter().map(String::as_str).collect();
+
+    // The row count is only known once every row has been fetched, so room
+    // for the longest possible header is left at the front and the real one
+    // is moved into place at the end
+    let placeholder = if options.format.lengthless {
+        Vec::new()
+    } else {
+        table_header(usize::MAX, &fields, options)?
+    };
+    let reserved = placeholder.len();
+    let mut out = ZendStringWriter::with_capacity(reserved);
+    out.push_bytes(&placeholder);
+
+    let mut ctx = EncodeContext::new(options);
+    let mut encoder = StreamEncoder::new(&mut out, options.format.clone());
+    if options.format.lengthless {
+        encoder
+            .table_header("", 0, &fields)
+            .map_err(|e| PhpException::default(e.to_string()))?;
+    }
+    let count = write_rows(stmt, &columns, &mut ctx, &mut encoder);
+    LAST_SUBSTITUTIONS.set(ctx.substitutions);
+    let count = count?;
+    encoder
+        .finish()
+        .map_err(|e| PhpException::default(e.to_string()))?;
+
+    if !options.format.lengthless {
+        let mut header = table_header(count, &fields, options)?;
+        if count == 0 {
+            header.pop();
+        }
+        out.replace_start(reserved, &header);
+    }
+    Ok(out.finish())
+}
+
+/// The root table header for `count` rows, followed by the line break that
+/// separates it from the first row.
+fn table_header(count: usize, fields: &[&str], options: &EncodeOptions) -> PhpResult<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut header = StreamEncoder::new(&mut out, options.format.clone());
+    header
+        .table_header("", count, fields)
+        .and_then(|()| header.finish())
+        .map_err(|e| PhpException::default(e.to_string()))?;
+    out.push(b'\n');
+    Ok(out)
+}
+
+/// Column names from `getColumnMeta()`; drivers without metadata support get
+/// the column positions instead.
+fn column_names(stmt: &ZendObject) -> PhpResult<Vec<String>> {
+    let count = stmt
+        .try_call_method("columnCount", vec![])
+        .map_err(php_error)?
+        .long()
+        .unwrap_or(0);
+
+    let mut names = Vec::with_capacity(usize::try_from(count).unwrap_or(0));
+    for column in 0..count {
+        let meta = stmt
+            .try_call_method("getColumnMeta", vec![&column])
+            .map_err(php_error)?;
+        let name = meta
+            .array()
+            .and_then(|meta| meta.get("name"))
+            .and_then(|name| name.string());
+        names.push(name.unwrap_or_else(|| column.to_string()));
+    }
+    Ok(names)
+}
+
+/// Fetches and writes rows until the statement is exhausted, returning how
+/// many were written.
+fn write_rows<W: io::Write>(
+    stmt: &ZendObject,
+    columns: &[String],
+    ctx: &mut EncodeContext,
+    encoder: &mut StreamEncoder<W>,
+) -> PhpResult<usize> {
+    let mut count = 0;
+    let mut cells = Vec::with_capacity(columns.len());
+    loop {
+        let row = stmt
+            .try_call_method("fetch", vec![&FETCH_NUM])
+            .map_err(php_error)?;
+        // `false` once there are no rows left
+        let Some(values) = row.array() else {
+            return Ok(count);
+        };
+
+        ctx.path.push(count.to_string());
+        cells.clear();
+        for (value, column) in values.values().zip(columns) {
+            // Only LOB streams and the like need the path, for the
+            // `unsupported` policy
+            let tracked = !is_scalar(value);
+            if tracked {
+                ctx.path.push(column.clone());
+            }
+            let cell = zval_to_toon_value_impl(value, 2, ctx);
+            if tracked {
+                ctx.path.pop();
+            }
+            let cell = cell?;
+            if matches!(cell, ToonValue::Array(_) | ToonValue::Map(_)) {
+                return Err(PhpException::default(format!(
+                    "Column {column} of row {count} is not a scalar value"
+                )));
+            }
+            cells.push(cell);
+        }
+        ctx.path.pop();
+
+        encoder
+            .table_row(&cells)
+            .map_err(|e| PhpException::default(e.to_string()))?;
+        count += 1;
+    }
+}
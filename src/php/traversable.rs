@@ -0,0 +1,278 @@
+use std::io;
+use std::ptr;
+
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendIterator, Zval};
+
+use super::options::{EncodeOptions, RowMismatch};
+use super::{pending_exception, zval_to_toon_value_impl, EncodeContext, LAST_SUBSTITUTIONS};
+use crate::toon::{StreamEncoder, ToonMap, ToonValue};
+
+extern "C" {
+    fn zend_iterator_dtor(iter: *mut ZendIterator);
+}
+
+/// The iterator of a `Traversable` object, released when dropped.
+struct PhpIterator<'a>(&'a mut ZendIterator);
+
+impl<'a> PhpIterator<'a> {
+    fn new(zval: &'a Zval) -> PhpResult<Self> {
+        zval.traversable().map(Self).ok_or_else(|| {
+            pending_exception().unwrap_or_else(|| {
+                PhpException::default("Could not iterate over object".to_string())
+            })
+        })
+    }
+
+    /// Visits every key and value from the start, rethrowing any exception
+    /// raised by the iterator itself (e.g. a generator that already ran).
+    fn try_for_each(&mut self, mut f: impl FnMut(Zval, &Zval) -> PhpResult<()>) -> PhpResult<()> {
+        if let Some(iter) = self.0.iter() {
+            for (key, value) in iter {
+                f(key, value)?;
+            }
+        }
+        pending_exception().map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for PhpIterator<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the iterator was created by `get_iterator` for this object
+        // and is not used past this point.
+        unsafe { zend_iterator_dtor(ptr::from_mut(self.0)) }
+    }
+}
+
+/// Fields of the first row of a `Traversable`, which the following rows are
+/// lined up with according to the `row_mismatch` option.
+struct RowShape {
+    fields: Vec<String>,
+    policy: RowMismatch,
+}
+
+impl RowShape {
+    /// Only non-empty maps of primitives can start a table.
+    fn of(row: &ToonValue, policy: RowMismatch) -> Option<Self> {
+        let ToonValue::Map(entries) = row else {
+            return None;
+        };
+        if entries.is_empty() || !entries.iter().all(|(_, value)| is_primitive(value)) {
+            return None;
+        }
+        Some(Self {
+            fields: entries.iter().map(|(key, _)| key.clone()).collect(),
+            policy,
+        })
+    }
+
+    /// The cells of `row` in field order, or `None` when the row is skipped.
+    fn cells(
+        &self,
+        row: ToonValue,
+        index: usize,
+        ctx: &EncodeContext,
+    ) -> PhpResult<Option<Vec<ToonValue>>> {
+        if let ToonValue::Map(mut entries) = row {
+            let same_fields = entries.len() == self.fields.len()
+                && entries.iter().all(|(key, _)| self.fields.contains(key));
+            if same_fields || self.policy == RowMismatch::Fill {
+                let cells: Vec<ToonValue> = self
+                    .fields
+                    .iter()
+                    .map(|field| entries.swap_remove(field).unwrap_or(ToonValue::Null))
+                    .collect();
+                if cells.iter().all(is_primitive) {
+                    return Ok(Some(cells));
+                }
+            }
+        }
+
+        match self.policy {
+            RowMismatch::Skip => Ok(None),
+            RowMismatch::Error | RowMismatch::Fill => {
+                let path = ctx.path.join(".");
+                let location = if path.is_empty() { "(root)" } else { &path };
+                Err(PhpException::default(format!(
+                    "Row {index} at path {location} does not match the fields of the first row"
+                )))
+            }
+        }
+    }
+
+    fn row(&self, cells: Vec<ToonValue>) -> ToonValue {
+        ToonValue::Map(self.fields.iter().cloned().zip(cells).collect())
+    }
+}
+
+fn is_primitive(value: &ToonValue) -> bool {
+    !matches!(value, ToonValue::Array(_) | ToonValue::Map(_))
+}
+
+/// Converts a `Traversable`: sequential keys give a list of rows lined up
+/// with the first one, anything else a map.
+pub fn traversable_to_toon_value(
+    zval: &Zval,
+    depth: usize,
+    ctx: &mut EncodeContext,
+) -> PhpResult<ToonValue> {
+    let mut entries = ToonMap::new();
+    let mut sequential = true;
+    PhpIterator::new(zval)?.try_for_each(|key, value| {
+        let key = match key.long() {
+            Some(idx) => {
+                sequential &= idx == entries.len() as i64;
+                idx.to_string()
+            }
+            None => {
+                sequential = false;
+                key.string().unwrap_or_default()
+            }
+        };
+        ctx.path.push(key.clone());
+        let val = zval_to_toon_value_impl(value, depth + 1, ctx);
+        ctx.path.pop();
+        entries.insert(key, val?);
+        Ok(())
+    })?;
+
+    if !sequential {
+        return Ok(ToonValue::Map(entries));
+    }
+    let rows: Vec<ToonValue> = entries.into_values().collect();
+    let Some(shape) = rows
+        .first()
+        .and_then(|first| RowShape::of(first, ctx.options.row_mismatch))
+    else {
+        return Ok(ToonValue::Array(rows));
+    };
+
+    let mut aligned = Vec::with_capacity(rows.len());
+    for (index, row) in rows.into_iter().enumerate() {
+        if let Some(cells) = shape.cells(row, index, ctx)? {
+            aligned.push(shape.row(cells));
+        }
+    }
+    Ok(ToonValue::Array(aligned))
+}
+
+/// Writes `input` with every `Traversable` at the root, or directly under a
+/// root array key, streamed into a lengthless table row by row instead of
+/// being collected first. Keys yielded by those `Traversable`s are ignored.
+pub fn stream<W: io::Write>(
+    input: &Zval,
+    options: &EncodeOptions,
+    encoder: &mut StreamEncoder<W>,
+) -> PhpResult<()> {
+    let mut ctx = EncodeContext::new(options);
+    let result = stream_impl(input, &mut ctx, encoder);
+    LAST_SUBSTITUTIONS.set(ctx.substitutions);
+    result
+}
+
+fn stream_impl<W: io::Write>(
+    input: &Zval,
+    ctx: &mut EncodeContext,
+    encoder: &mut StreamEncoder<W>,
+) -> PhpResult<()> {
+    let input = input.dereference();
+    if input.is_traversable() {
+        return stream_table("", input, 0, ctx, encoder);
+    }
+
+    match input.array() {
+        Some(ht)
+            if ht
+                .values()
+                .any(|value| value.dereference().is_traversable()) =>
+        {
+            ctx.enter(ptr::from_ref(ht).cast(), |ctx| {
+                for (key, value) in ht.iter() {
+                    let key = key.to_string();
+                    let value = value.dereference();
+                    if value.is_traversable() {
+                        stream_table(&key, value, 1, ctx, encoder)?;
+                        continue;
+                    }
+                    ctx.path.push(key.clone());
+                    let val = zval_to_toon_value_impl(value, 1, ctx);
+                    ctx.path.pop();
+                    write(encoder.entry(&key, &val?))?;
+                }
+                Ok(())
+            })
+        }
+        _ => {
+            let val = zval_to_toon_value_impl(input, 0, ctx)?;
+            write(encoder.value(&val))
+        }
+    }
+}
+
+/// Streams the rows of a `Traversable` as a table under `key` (empty at the
+/// root). When the first row cannot start a table the rows are collected
+/// and written like any other list.
+fn stream_table<W: io::Write>(
+    key: &str,
+    zval: &Zval,
+    depth: usize,
+    ctx: &mut EncodeContext,
+    encoder: &mut StreamEncoder<W>,
+) -> PhpResult<()> {
+    if !key.is_empty() {
+        ctx.path.push(key.to_string());
+    }
+    let container = zval
+        .object()
+        .map_or(ptr::null(), |obj| ptr::from_ref(obj).cast());
+    let result = ctx.enter(container, |ctx| {
+        let mut shape: Option<RowShape> = None;
+        let mut collected: Vec<ToonValue> = Vec::new();
+        let mut index = 0;
+        PhpIterator::new(zval)?.try_for_each(|_, value| {
+            ctx.path.push(index.to_string());
+            let row = zval_to_toon_value_impl(value, depth + 1, ctx);
+            ctx.path.pop();
+            let row = row?;
+
+            if let Some(shape) = &shape {
+                if let Some(cells) = shape.cells(row, index, ctx)? {
+                    write(encoder.table_row(&cells))?;
+                }
+            } else if let Some(first) = collected
+                .is_empty()
+                .then(|| RowShape::of(&row, ctx.options.row_mismatch))
+                .flatten()
+            {
+                let fields: Vec<&str> = first.fields.iter().map(String::as_str).collect();
+                write(encoder.table_header(key, 0, &fields))?;
+                if let Some(cells) = first.cells(row, index, ctx)? {
+                    write(encoder.table_row(&cells))?;
+                }
+                shape = Some(first);
+            } else {
+                collected.push(row);
+            }
+            index += 1;
+            Ok(())
+        })?;
+
+        if shape.is_some() {
+            return Ok(());
+        }
+        let rows = ToonValue::Array(collected);
+        if key.is_empty() {
+            write(encoder.value(&rows))
+        } else {
+            write(encoder.entry(key, &rows))
+        }
+    });
+    if !key.is_empty() {
+        ctx.path.pop();
+    }
+    result
+}
+
+fn write(result: io::Result<()>) -> PhpResult<()> {
+    result.map_err(|e| PhpException::default(e.to_string()))
+}
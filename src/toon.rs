@@ -1,3 +1,30 @@
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::{fmt, io};
+
+use indexmap::map::Entry;
+use indexmap::IndexMap;
+
+#[cfg(feature = "serde")]
+mod de;
+mod error;
+mod reader;
+#[cfg(feature = "serde")]
+mod ser;
+mod value;
+
+#[cfg(feature = "serde")]
+pub use de::{from_reader, from_str};
+pub use error::{CountKind, Span, ToonError};
+pub use reader::{Event, IoSource, Reader, Source, StrSource};
+#[cfg(feature = "serde")]
+pub use ser::{to_string, to_writer, SerdeError};
+
+/// Entries of a [`ToonValue::Map`], in document order, with hashed lookup
+/// by key.
+pub type ToonMap = IndexMap<String, ToonValue>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ToonValue {
     Null,
@@ -6,681 +33,3470 @@ pub enum ToonValue {
     Float(f64),
     String(String),
     Array(Vec<ToonValue>),
-    Map(Vec<(String, ToonValue)>), // Ordered map to match PHP array behavior
+    Map(ToonMap), // Ordered map to match PHP array behavior
 }
 
-// --- Parser ---
+/// A parsed document borrowing its keys and strings from the input, see
+/// [`parse_borrowed`]. Only strings whose escapes had to be resolved own
+/// their text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BorrowedValue<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(Cow<'a, str>),
+    Array(Vec<BorrowedValue<'a>>),
+    Map(IndexMap<Cow<'a, str>, BorrowedValue<'a>>),
+}
 
-pub fn parse(input: &str) -> anyhow::Result<ToonValue> {
-    let lines: Vec<&str> = input.lines().collect();
-    if lines.is_empty() {
-        return Ok(ToonValue::Null);
+impl BorrowedValue<'_> {
+    /// Copies what is still borrowed, detaching the value from the input.
+    pub fn into_owned(self) -> ToonValue {
+        match self {
+            Self::Null => ToonValue::Null,
+            Self::Bool(b) => ToonValue::Bool(b),
+            Self::Int(i) => ToonValue::Int(i),
+            Self::Float(f) => ToonValue::Float(f),
+            Self::String(s) => ToonValue::String(s.into_owned()),
+            Self::Array(items) => {
+                ToonValue::Array(items.into_iter().map(Self::into_owned).collect())
+            }
+            Self::Map(entries) => ToonValue::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect(),
+            ),
+        }
     }
+}
+
+/// Separator placed between the items of an inline list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Delimiter {
+    #[default]
+    Comma,
+    Tab,
+    Pipe,
+}
 
-    // Check if it's a single line value and not a key-value pair
-    if lines.len() == 1 {
-        let line = lines[0].trim();
-        if !line.contains(':') && !line.is_empty() {
-            return Ok(parse_value(line));
+impl Delimiter {
+    /// Accepts either the delimiter character itself or its name
+    /// (`comma`, `tab`, `pipe`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "," | "comma" => Some(Self::Comma),
+            "\t" | "tab" => Some(Self::Tab),
+            "|" | "pipe" => Some(Self::Pipe),
+            _ => None,
         }
     }
 
-    parse_lines(&lines, 0, 0).map(|(val, _)| val)
-}
+    pub fn as_char(self) -> char {
+        match self {
+            Self::Comma => ',',
+            Self::Tab => '\t',
+            Self::Pipe => '|',
+        }
+    }
 
-fn parse_lines(
-    lines: &[&str],
-    start_idx: usize,
-    base_indent: usize,
-) -> anyhow::Result<(ToonValue, usize)> {
-    parse_lines_impl(lines, start_idx, base_indent, 0)
+    pub(crate) fn separator(self) -> &'static str {
+        match self {
+            Self::Comma => ", ",
+            Self::Tab => "\t",
+            Self::Pipe => "|",
+        }
+    }
 }
 
-fn parse_lines_impl(
-    lines: &[&str],
-    start_idx: usize,
-    base_indent: usize,
-    depth: usize,
-) -> anyhow::Result<(ToonValue, usize)> {
-    const MAX_PARSE_DEPTH: usize = 100;
-    if depth > MAX_PARSE_DEPTH {
-        return Err(anyhow::anyhow!("Parse depth limit exceeded"));
-    }
+/// Version of the TOON specification the parser and encoder follow.
+pub const SPEC_VERSION: &str = "1.0";
 
-    let mut map = Vec::new();
-    let mut i = start_idx;
+// --- Parser ---
 
-    while i < lines.len() {
-        let line = lines[i];
+/// Hard nesting limit of the parser when no `max_depth` is given.
+pub const MAX_PARSE_DEPTH: usize = 100;
+
+/// Ceiling of any `max_depth`. Nested inline lists are read recursively, so
+/// larger limits are capped at this one instead of letting a deep enough
+/// document overflow the stack.
+pub const MAX_DEPTH_LIMIT: usize = 1000;
+
+/// What to do when a map repeats one of its keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeys {
+    /// Fail with a parse error.
+    Error,
+    /// Keep the first value and ignore the later ones.
+    First,
+    /// Keep the last value, at the position of the first, like assigning to
+    /// an existing key of a PHP array.
+    #[default]
+    Last,
+}
 
-        // Skip empty lines
-        if line.trim().is_empty() {
-            i += 1;
-            continue;
+impl DuplicateKeys {
+    /// Accepts `error`, `first` or `last`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Self::Error),
+            "first" => Some(Self::First),
+            "last" => Some(Self::Last),
+            _ => None,
         }
+    }
 
-        let indent = line.len() - line.trim_start().len();
-        if indent < base_indent {
-            // End of current block
-            break;
+    /// Fails for [`Error`](Self::Error) when the map holding `taken` keys
+    /// receives `key` again. Checked as each key arrives, while the parser
+    /// is still on its line.
+    pub(crate) fn check(
+        self,
+        key: &str,
+        taken: impl FnOnce(&str) -> bool,
+    ) -> Result<(), ToonError> {
+        if self == Self::Error && taken(key) {
+            return Err(ToonError::DuplicateKey {
+                span: Span::default(),
+                key: key.to_string(),
+            });
         }
+        Ok(())
+    }
 
-        let trimmed = line.trim();
-        if let Some((key_part, val_part)) = trimmed.split_once(':') {
-            let key = key_part.trim().to_string();
-            let val_str = val_part.trim();
-
-            if val_str.is_empty() {
-                // Nested object or empty
-                // Check next line to see if it's a child
-                if i + 1 < lines.len() {
-                    let next_line = lines[i + 1];
-                    if !next_line.trim().is_empty() {
-                        let next_indent = next_line.len() - next_line.trim_start().len();
-
-                        if next_indent > indent {
-                            let (nested_val, consumed) =
-                                parse_lines_impl(lines, i + 1, next_indent, depth + 1)?;
-                            map.push((key, nested_val));
-                            i = consumed;
-                            continue;
-                        }
-                    }
-                }
-                // No children, treat as empty map (or null? spec is vague, assuming empty map for container)
-                map.push((key, ToonValue::Map(Vec::new())));
-            } else {
-                // Inline value
-                map.push((key, parse_value(val_str)));
+    /// Adds an entry to `map`, keeping the existing value of a taken key for
+    /// [`First`](Self::First) and replacing it otherwise.
+    fn insert<K: Hash + Eq, V>(self, map: &mut IndexMap<K, V>, key: K, value: V) {
+        match map.entry(key) {
+            Entry::Occupied(_) if self == Self::First => {}
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
             }
-        } else {
-            // Line without colon?
-            // Could be a continuation or error. For now, ignore or treat as string key with null?
-            // Spec says "Key-value pairs with colons".
         }
-        i += 1;
     }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOptions {
+    pub delimiter: Delimiter,
+    /// Reject lines without a key, unexpected indentation, unterminated
+    /// strings and unknown escapes instead of reading past them, and arrays
+    /// whose length differs from their header.
+    pub strict: bool,
+    /// Deepest nesting of maps and lists, the root being 0. Capped at
+    /// [`MAX_DEPTH_LIMIT`].
+    pub max_depth: usize,
+    pub duplicate_keys: DuplicateKeys,
+}
 
-    Ok((ToonValue::Map(map), i))
+impl ParseOptions {
+    /// `max_depth`, capped at [`MAX_DEPTH_LIMIT`].
+    pub fn depth_limit(&self) -> usize {
+        self.max_depth.min(MAX_DEPTH_LIMIT)
+    }
 }
 
-fn parse_value(s: &str) -> ToonValue {
-    let s = s.trim();
-    if s == "true" {
-        return ToonValue::Bool(true);
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: Delimiter::default(),
+            strict: false,
+            max_depth: MAX_PARSE_DEPTH,
+            duplicate_keys: DuplicateKeys::default(),
+        }
     }
-    if s == "false" {
-        return ToonValue::Bool(false);
+}
+
+/// A primitive value handed to a [`Sink`]. Strings borrow from the input
+/// unless they had escapes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scalar<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(Cow<'a, str>),
+}
+
+impl Scalar<'_> {
+    /// Copies a borrowed string, detaching the value from the input.
+    pub fn into_owned(self) -> Scalar<'static> {
+        match self {
+            Self::Null => Scalar::Null,
+            Self::Bool(b) => Scalar::Bool(b),
+            Self::Int(i) => Scalar::Int(i),
+            Self::Float(f) => Scalar::Float(f),
+            Self::Str(s) => Scalar::Str(Cow::Owned(s.into_owned())),
+        }
     }
-    if s == "null" {
-        return ToonValue::Null;
+}
+
+impl From<Scalar<'_>> for ToonValue {
+    fn from(value: Scalar<'_>) -> Self {
+        match value {
+            Scalar::Null => Self::Null,
+            Scalar::Bool(b) => Self::Bool(b),
+            Scalar::Int(i) => Self::Int(i),
+            Scalar::Float(f) => Self::Float(f),
+            Scalar::Str(s) => Self::String(s.into_owned()),
+        }
     }
-    if let Ok(i) = s.parse::<i64>() {
-        return ToonValue::Int(i);
+}
+
+impl<'a> From<Scalar<'a>> for BorrowedValue<'a> {
+    fn from(value: Scalar<'a>) -> Self {
+        match value {
+            Scalar::Null => Self::Null,
+            Scalar::Bool(b) => Self::Bool(b),
+            Scalar::Int(i) => Self::Int(i),
+            Scalar::Float(f) => Self::Float(f),
+            Scalar::Str(s) => Self::String(s),
+        }
     }
-    if let Ok(f) = s.parse::<f64>() {
-        return ToonValue::Float(f);
+}
+
+/// Receives a document from the parser as it is read, so callers can build
+/// their own representation of it instead of going through [`ToonValue`].
+///
+/// Values nest like the document: a map is `begin_map`, then a `key` and a
+/// value for each entry, then `end`. Errors returned here stop the parser;
+/// those without a position get the one of the event they failed on, and
+/// [`ToonError::sink`] wraps the sink's own errors. Keys and strings borrow
+/// from the input (`'a`) whenever they can.
+pub trait Sink<'a> {
+    /// Opens a map. `len_hint` is its number of entries when known, else 0.
+    fn begin_map(&mut self, len_hint: usize) -> Result<(), ToonError>;
+    /// Opens a list. `len_hint` is its number of items when known, else 0.
+    fn begin_list(&mut self, len_hint: usize) -> Result<(), ToonError>;
+    /// Key of the next value of the innermost map.
+    fn key(&mut self, key: Cow<'a, str>) -> Result<(), ToonError>;
+    fn scalar(&mut self, value: Scalar<'a>) -> Result<(), ToonError>;
+    /// Closes the innermost map or list.
+    fn end(&mut self) -> Result<(), ToonError>;
+}
+
+/// A [`Sink`] building the [`ToonValue`] returned by [`parse_with`].
+#[derive(Debug, Default)]
+pub struct ValueBuilder {
+    /// Open containers, with the key of the next entry for maps.
+    stack: Vec<(ToonValue, Option<String>)>,
+    root: Option<ToonValue>,
+    duplicates: DuplicateKeys,
+}
+
+impl ValueBuilder {
+    pub fn new(duplicates: DuplicateKeys) -> Self {
+        Self {
+            duplicates,
+            ..Self::default()
+        }
     }
 
-    // Handle quoted strings
-    if s.starts_with('"') && s.ends_with('"') && s.len() >= 2 {
-        // Simple unescape: replace \" with " and \\ with \
-        // For a full implementation, use a proper unescape function
-        let inner = &s[1..s.len() - 1];
-        let unescaped = inner
-            .replace("\\\"", "\"")
-            .replace("\\n", "\n")
-            .replace("\\\\", "\\");
-        return ToonValue::String(unescaped);
+    /// The value built so far; `Null` when nothing was received.
+    pub fn finish(self) -> ToonValue {
+        self.root.unwrap_or(ToonValue::Null)
     }
 
-    // Check for wrapped list [...]
-    if s.starts_with('[') && s.ends_with(']') {
-        // Check if it's a single enclosing pair
-        let mut depth = 0;
-        let mut enclosed = true;
-        for (i, c) in s.chars().enumerate() {
-            match c {
-                '[' => depth += 1,
-                ']' => {
-                    depth -= 1;
-                    if depth == 0 && i < s.len() - 1 {
-                        enclosed = false;
-                        break;
-                    }
-                }
-                _ => {}
+    fn push(&mut self, value: ToonValue) {
+        match self.stack.last_mut() {
+            None => self.root = Some(value),
+            Some((ToonValue::Map(entries), key)) => {
+                let key = key.take().unwrap_or_default();
+                self.duplicates.insert(entries, key, value);
             }
+            Some((ToonValue::Array(items), _)) => items.push(value),
+            Some(_) => {}
         }
-        if enclosed {
-            let inner = &s[1..s.len() - 1];
-            return parse_list_content(inner);
+    }
+}
+
+impl Sink<'_> for ValueBuilder {
+    fn begin_map(&mut self, len_hint: usize) -> Result<(), ToonError> {
+        self.stack
+            .push((ToonValue::Map(ToonMap::with_capacity(len_hint)), None));
+        Ok(())
+    }
+
+    fn begin_list(&mut self, len_hint: usize) -> Result<(), ToonError> {
+        self.stack
+            .push((ToonValue::Array(Vec::with_capacity(len_hint)), None));
+        Ok(())
+    }
+
+    fn key(&mut self, key: Cow<'_, str>) -> Result<(), ToonError> {
+        if let Some((container, pending)) = self.stack.last_mut() {
+            let duplicates = self.duplicates;
+            duplicates.check(
+                &key,
+                |key| matches!(container, ToonValue::Map(entries) if entries.contains_key(key)),
+            )?;
+            *pending = Some(key.into_owned());
         }
+        Ok(())
+    }
+
+    fn scalar(&mut self, value: Scalar<'_>) -> Result<(), ToonError> {
+        self.push(value.into());
+        Ok(())
     }
 
-    // Handle lists: comma separated values
-    if s.contains(',') {
-        return parse_list_content(s);
+    fn end(&mut self) -> Result<(), ToonError> {
+        if let Some((value, _)) = self.stack.pop() {
+            self.push(value);
+        }
+        Ok(())
     }
+}
 
-    ToonValue::String(s.to_string())
+/// A [`Sink`] building the [`BorrowedValue`] returned by [`parse_borrowed`].
+#[derive(Debug, Default)]
+struct BorrowedBuilder<'a> {
+    stack: Vec<(BorrowedValue<'a>, Option<Cow<'a, str>>)>,
+    root: Option<BorrowedValue<'a>>,
+    duplicates: DuplicateKeys,
 }
 
-fn parse_list_content(s: &str) -> ToonValue {
-    let parts = split_smart(s);
-    let mut items = Vec::with_capacity(parts.len());
-    for p in parts {
-        items.push(parse_value(&p));
+impl<'a> BorrowedBuilder<'a> {
+    fn finish(self) -> BorrowedValue<'a> {
+        self.root.unwrap_or(BorrowedValue::Null)
+    }
+
+    fn push(&mut self, value: BorrowedValue<'a>) {
+        match self.stack.last_mut() {
+            None => self.root = Some(value),
+            Some((BorrowedValue::Map(entries), key)) => {
+                let key = key.take().unwrap_or_default();
+                self.duplicates.insert(entries, key, value);
+            }
+            Some((BorrowedValue::Array(items), _)) => items.push(value),
+            Some(_) => {}
+        }
     }
-    ToonValue::Array(items)
 }
 
-fn split_smart(s: &str) -> Vec<String> {
-    let mut parts = Vec::new();
-    let mut current = String::new();
-    let mut depth = 0;
-    let mut in_quote = false;
-    let mut escape = false;
+impl<'a> Sink<'a> for BorrowedBuilder<'a> {
+    fn begin_map(&mut self, len_hint: usize) -> Result<(), ToonError> {
+        self.stack
+            .push((BorrowedValue::Map(IndexMap::with_capacity(len_hint)), None));
+        Ok(())
+    }
+
+    fn begin_list(&mut self, len_hint: usize) -> Result<(), ToonError> {
+        self.stack
+            .push((BorrowedValue::Array(Vec::with_capacity(len_hint)), None));
+        Ok(())
+    }
 
-    for c in s.chars() {
-        if escape {
-            current.push(c);
-            escape = false;
-            continue;
-        }
-        if c == '\\' {
-            current.push(c);
-            escape = true;
-            continue;
+    fn key(&mut self, key: Cow<'a, str>) -> Result<(), ToonError> {
+        if let Some((container, pending)) = self.stack.last_mut() {
+            let duplicates = self.duplicates;
+            duplicates.check(
+                &key,
+                |key| matches!(container, BorrowedValue::Map(entries) if entries.contains_key(key)),
+            )?;
+            *pending = Some(key);
         }
+        Ok(())
+    }
 
-        if in_quote {
-            current.push(c);
-            if c == '"' {
-                in_quote = false;
-            }
-            continue;
+    fn scalar(&mut self, value: Scalar<'a>) -> Result<(), ToonError> {
+        self.push(value.into());
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), ToonError> {
+        if let Some((value, _)) = self.stack.pop() {
+            self.push(value);
         }
+        Ok(())
+    }
+}
 
-        match c {
-            '"' => {
-                in_quote = true;
-                current.push(c);
+pub fn parse(input: &str) -> Result<ToonValue, ToonError> {
+    parse_with(input, &ParseOptions::default())
+}
+
+pub fn parse_with(input: &str, opts: &ParseOptions) -> Result<ToonValue, ToonError> {
+    let mut builder = ValueBuilder::new(opts.duplicate_keys);
+    parse_into(input, opts, &mut builder)?;
+    Ok(builder.finish())
+}
+
+/// Like [`parse_with`], without copying keys and strings out of `input`.
+pub fn parse_borrowed<'a>(
+    input: &'a str,
+    opts: &ParseOptions,
+) -> Result<BorrowedValue<'a>, ToonError> {
+    let mut builder = BorrowedBuilder {
+        duplicates: opts.duplicate_keys,
+        ..BorrowedBuilder::default()
+    };
+    parse_into(input, opts, &mut builder)?;
+    Ok(builder.finish())
+}
+
+/// Parses `input` into `sink`, which receives exactly one root value.
+/// Errors returned by `sink` come back as [`ToonError::Sink`].
+pub fn parse_into<'a, S: Sink<'a>>(
+    input: &'a str,
+    opts: &ParseOptions,
+    sink: &mut S,
+) -> Result<(), ToonError> {
+    feed(Reader::new(input, opts.clone()), sink, input.len())
+}
+
+/// Sends `events` to `sink`, locating its errors at the event they stopped
+/// at. `max_len` caps the list lengths taken from array headers.
+fn feed<'a, S: Sink<'a>>(
+    events: impl IntoIterator<Item = Result<(Event<'a>, Span), ToonError>>,
+    sink: &mut S,
+    max_len: usize,
+) -> Result<(), ToonError> {
+    // Number of fields of the rows of each open list, 0 for other lists and
+    // for maps
+    let mut widths = Vec::new();
+    for event in events {
+        let (event, span) = event?;
+        let result = match event {
+            Event::StartMap => {
+                let width = widths.last().copied().unwrap_or(0);
+                widths.push(0);
+                sink.begin_map(width)
             }
-            '[' => {
-                depth += 1;
-                current.push(c);
+            Event::StartArray { len, fields, .. } => {
+                widths.push(fields.map_or(0, |fields| fields.len()));
+                sink.begin_list(len.unwrap_or(0).min(max_len))
             }
-            ']' => {
-                if depth > 0 {
-                    depth -= 1;
-                }
-                current.push(c);
+            Event::Key(key) => sink.key(key),
+            Event::Scalar(value) => sink.scalar(value),
+            Event::End => {
+                widths.pop();
+                sink.end()
             }
-            ',' => {
-                if depth == 0 {
-                    parts.push(current.trim().to_string());
-                    current.clear();
-                } else {
-                    current.push(c);
-                }
+        };
+        result.map_err(|e| e.located(span))?;
+    }
+    Ok(())
+}
+
+/// Builds the value of `events`, for callers that only parse part of a
+/// document.
+fn build<'a>(
+    events: impl IntoIterator<Item = Result<(Event<'a>, Span), ToonError>>,
+    opts: &ParseOptions,
+    max_len: usize,
+) -> Result<ToonValue, ToonError> {
+    let mut builder = ValueBuilder::new(opts.duplicate_keys);
+    feed(events, &mut builder, max_len)?;
+    Ok(builder.finish())
+}
+
+/// A cursor over the lines of a document, handing out slices of it. Lines
+/// end at `\n` or `\r\n`, like with `str::lines`.
+#[derive(Debug, Clone, Copy)]
+struct Lines<'a> {
+    input: &'a str,
+    line: Option<&'a str>,
+    /// Offset of the line after the current one.
+    next: usize,
+    /// 1-based number of the current line, for error messages.
+    number: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(input: &'a str) -> Self {
+        let mut lines = Self {
+            input,
+            line: None,
+            next: 0,
+            number: 0,
+        };
+        lines.advance();
+        lines
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.line
+    }
+
+    /// Whether the current line is the last one.
+    fn is_last(&self) -> bool {
+        self.next >= self.input.len()
+    }
+
+    fn advance(&mut self) {
+        let rest = &self.input[self.next..];
+        self.number += 1;
+        if rest.is_empty() {
+            self.line = None;
+            return;
+        }
+        let (line, len) = match rest.find('\n') {
+            Some(end) => (
+                rest[..end].strip_suffix('\r').unwrap_or(&rest[..end]),
+                end + 1,
+            ),
+            None => (rest, rest.len()),
+        };
+        self.line = Some(line);
+        self.next += len;
+    }
+
+    /// Moves past the lines indented at least `min_indent`, and the empty
+    /// lines among them.
+    fn skip_block(&mut self, min_indent: usize) {
+        while let Some(line) = self.peek() {
+            if !line.trim().is_empty() && indent_of(line) < min_indent {
+                break;
             }
-            _ => current.push(c),
+            self.advance();
+        }
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Whether the document is a single array with an empty key and nothing else
+/// but lines the parser would skip.
+fn is_root_array(mut lines: Lines<'_>, opts: &ParseOptions) -> bool {
+    let mut only_blank = true;
+    while let Some(line) = lines.peek() {
+        if line.contains(':') {
+            break;
         }
+        only_blank &= line.trim().is_empty();
+        lines.advance();
+    }
+
+    let Some(line) = lines.peek() else {
+        return false;
+    };
+    let header = line
+        .trim()
+        .split_once(':')
+        .and_then(|(key_part, _)| parse_array_header(key_part, opts.delimiter));
+    let Some(header) = header.filter(|header| header.key.is_empty()) else {
+        return false;
+    };
+
+    let indent = indent_of(line);
+    lines.advance();
+    if header.fields.is_some() {
+        lines.skip_block(indent + 1);
     }
-    if !current.trim().is_empty() {
-        parts.push(current.trim().to_string());
+    while let Some(line) = lines.peek() {
+        // Strict mode rejects anything around the array, so it is read as a map
+        if line.contains(':') || (opts.strict && !line.trim().is_empty()) {
+            return false;
+        }
+        lines.advance();
     }
-    
-    parts
+    !opts.strict || (only_blank && indent == 0)
 }
 
-// --- Encoder ---
+/// An array header: `key[N]: a,b` for inline arrays or `key[N]{f1,f2}:` for
+/// tabular ones. A delimiter character after `N` (`[N|]`, `[N\t]`) overrides
+/// the default one for this array.
+#[derive(Debug, Clone, PartialEq)]
+struct ArrayHeader<'a> {
+    key: &'a str,
+    /// `None` for a lengthless `[]` header.
+    len: Option<usize>,
+    delimiter: Delimiter,
+    fields: Option<Vec<Cow<'a, str>>>,
+}
 
-pub fn encode(val: &ToonValue) -> String {
-    let mut out = String::new();
-    encode_recursive_impl(val, 0, &mut out, 0);
-    out.trim_end().to_string()
+fn parse_array_header(key_part: &str, default: Delimiter) -> Option<ArrayHeader<'_>> {
+    let key_part = key_part.trim();
+    let open = key_part.find('[')?;
+    let close = open + key_part[open..].find(']')?;
+    let spec = key_part[open + 1..close].trim_start_matches('#');
+    let (digits, delimiter) = match spec.chars().last() {
+        Some('|') => (&spec[..spec.len() - 1], Delimiter::Pipe),
+        Some('\t') => (&spec[..spec.len() - 1], Delimiter::Tab),
+        _ => (spec, default),
+    };
+    let len = if digits.is_empty() {
+        None
+    } else {
+        Some(digits.parse().ok()?)
+    };
+
+    let rest = &key_part[close + 1..];
+    let fields = if rest.is_empty() {
+        None
+    } else {
+        let inner = rest.strip_prefix('{')?.strip_suffix('}')?;
+        let fields = Inline::new(inner, Span::default());
+        let fields = fields
+            .parts(0, inner.len(), delimiter)
+            .into_iter()
+            .map(|(start, end)| unquote(&inner[start..end]))
+            .collect();
+        Some(fields)
+    };
+
+    Some(ArrayHeader {
+        key: key_part[..open].trim(),
+        len,
+        delimiter,
+        fields,
+    })
+}
+
+/// Parses one row of a tabular array into a map keyed by `fields`.
+fn parse_row(
+    line: &str,
+    fields: &[Cow<'_, str>],
+    delimiter: Delimiter,
+    line_no: usize,
+    opts: &ParseOptions,
+) -> Result<ToonValue, ToonError> {
+    let mut events = VecDeque::new();
+    parse_row_into(line, fields, delimiter, line_no, 0, opts, &mut events)?;
+    build(events.into_iter().map(Ok), opts, 0)
 }
 
-fn encode_recursive_impl(val: &ToonValue, indent: usize, out: &mut String, depth: usize) {
-    const MAX_ENCODE_DEPTH: usize = 100;
-    if depth > MAX_ENCODE_DEPTH {
-        out.push_str("[MaxDepthExceeded]");
-        return;
+/// Events of one row of a tabular array; `depth` is the depth of the row
+/// itself.
+fn parse_row_into<'a>(
+    line: &'a str,
+    fields: &[Cow<'a, str>],
+    delimiter: Delimiter,
+    line_no: usize,
+    depth: usize,
+    opts: &ParseOptions,
+    out: &mut VecDeque<(Event<'a>, Span)>,
+) -> Result<(), ToonError> {
+    let at = Span::new(line_no, indent_of(line) + 1);
+    let line = line.trim();
+    let cells = Inline::new(line, at);
+    let spans = cells.parts(0, line.len(), delimiter);
+    if spans.len() != fields.len() {
+        return Err(ToonError::CountMismatch {
+            span: at,
+            kind: CountKind::Fields,
+            expected: fields.len(),
+            found: spans.len(),
+        });
     }
+    out.push_back((Event::StartMap, at));
+    for (field, (start, end)) in fields.iter().zip(spans) {
+        out.push_back((Event::Key(field.clone()), at.advance(start)));
+        cells.emit(start, end, delimiter, depth + 1, opts, out)?;
+    }
+    out.push_back((Event::End, at.advance(line.len())));
+    Ok(())
+}
 
-    let prefix = " ".repeat(indent);
+/// One step of a path into a document: a map key or a list index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
 
-    match val {
-        ToonValue::Map(entries) => {
-            for (key, value) in entries {
-                out.push_str(&prefix);
-                out.push_str(key);
+impl PathSegment {
+    /// Index segments also match maps keyed by index (`0:`, `1:`).
+    fn matches_key(&self, key: &str) -> bool {
+        match self {
+            Self::Key(name) => name == key,
+            Self::Index(idx) => key.parse() == Ok(*idx),
+        }
+    }
 
-                match value {
-                    ToonValue::Map(_) => {
-                        out.push_str(":\n");
-                        encode_recursive_impl(value, indent + 2, out, depth + 1);
-                    }
-                    _ => {
-                        out.push_str(": ");
-                        encode_recursive_impl(value, 0, out, depth + 1); // 0 indent because it's inline
+    /// Position of the entry this segment selects. Only index segments scan
+    /// the keys, since `01` or `+1` also parse as the index.
+    fn find_in(&self, entries: &ToonMap) -> Option<usize> {
+        match self {
+            Self::Key(name) => entries.get_index_of(name),
+            Self::Index(_) => entries.keys().position(|key| self.matches_key(key)),
+        }
+    }
+}
+
+/// Splits a path such as `settings.llm.model`, `users[0].name` or
+/// `users.0.name` into segments. Keys containing `.` or `[` can be quoted:
+/// `"v1.2".notes`.
+pub fn split_path(path: &str) -> Result<Vec<PathSegment>, ToonError> {
+    let mut segments = Vec::new();
+    let mut key = String::new();
+    let mut quoted = false;
+    let mut chars = path.chars();
+    // Points at the character after the one last read
+    let invalid = |chars: &std::str::Chars| ToonError::InvalidPath {
+        span: Span::new(1, path.len() - chars.as_str().len()),
+        path: path.to_string(),
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => push_key(&mut segments, &mut key, &mut quoted),
+            '"' if key.is_empty() && !quoted => {
+                loop {
+                    match chars.next().ok_or_else(|| invalid(&chars))? {
+                        '"' => break,
+                        '\\' => key.push(chars.next().ok_or_else(|| invalid(&chars))?),
+                        c => key.push(c),
                     }
                 }
+                quoted = true;
             }
+            '[' => {
+                push_key(&mut segments, &mut key, &mut quoted);
+                let digits: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                segments.push(PathSegment::Index(
+                    digits.trim().parse().map_err(|_| invalid(&chars))?,
+                ));
+            }
+            _ if quoted => return Err(invalid(&chars)),
+            c => key.push(c),
         }
-        ToonValue::Array(items) => {
-            let s = items
-                .iter()
-                .map(|v| value_to_string(v))
-                .collect::<Vec<_>>()
-                .join(", ");
-            out.push_str(&s);
-            out.push('\n');
-        }
-        _ => {
-            out.push_str(&value_to_string(val));
-            out.push('\n');
+    }
+    push_key(&mut segments, &mut key, &mut quoted);
+    Ok(segments)
+}
+
+/// Ends the key being read, if any; unquoted digits are a list index.
+fn push_key(segments: &mut Vec<PathSegment>, key: &mut String, quoted: &mut bool) {
+    if std::mem::take(quoted) {
+        segments.push(PathSegment::Key(std::mem::take(key)));
+    } else if !key.is_empty() {
+        let segment = match key.parse() {
+            Ok(idx) if key.bytes().all(|b| b.is_ascii_digit()) => PathSegment::Index(idx),
+            _ => PathSegment::Key(key.clone()),
+        };
+        segments.push(segment);
+        key.clear();
+    }
+}
+
+/// Decodes only the value at `path` (see [`split_path`]), or `None` when
+/// there is none. Sibling blocks are skipped by their indentation without
+/// being parsed, so their contents are not validated either.
+pub fn parse_path(
+    input: &str,
+    path: &str,
+    opts: &ParseOptions,
+) -> Result<Option<ToonValue>, ToonError> {
+    let segments = split_path(path)?;
+    if segments.is_empty() {
+        return parse_with(input, opts).map(Some);
+    }
+
+    let mut lines = Lines::new(input);
+    if let Some(line) = lines.peek().filter(|line| !line.contains(':')) {
+        if lines.is_last() {
+            let at = Span::new(lines.number, indent_of(line) + 1);
+            let value = parse_value_with(line.trim(), at, opts.delimiter, 0, opts)?;
+            return Ok(select(value, &segments));
         }
     }
+    find_in_block(&mut lines, 0, &segments, 0, opts)
 }
 
-fn value_to_string(val: &ToonValue) -> String {
-    match val {
-        ToonValue::Null => "null".to_string(),
-        ToonValue::Bool(b) => b.to_string(),
-        ToonValue::Int(i) => i.to_string(),
-        ToonValue::Float(f) => f.to_string(),
-        ToonValue::String(s) => {
-            // Always quote empty strings
-            if s.is_empty() {
-                return "\"\"".to_string();
-            }
-            // Quote if contains special chars
-            if s.contains('\n')
-                || s.contains(':')
-                || s.contains(',')
-                || s.contains('"')
-                || s.trim() != s
-                || (s.starts_with('[') && s.ends_with(']'))
-            {
-                // Simple escape
-                let escaped = s
-                    .replace('\\', "\\\\")
-                    .replace('"', "\\\"")
-                    .replace('\n', "\\n");
-                format!("\"{}\"", escaped)
-            } else {
-                s.as_str().to_string()
+/// Looks for `segments` in the map whose entries start at the current line.
+/// Several entries with the same key are handled like [`parse_with`] does,
+/// following [`ParseOptions::duplicate_keys`]; only the maps along the path
+/// are checked for [`DuplicateKeys::Error`].
+fn find_in_block(
+    lines: &mut Lines,
+    base_indent: usize,
+    segments: &[PathSegment],
+    depth: usize,
+    opts: &ParseOptions,
+) -> Result<Option<ToonValue>, ToonError> {
+    if depth > opts.depth_limit() {
+        return Err(ToonError::DepthExceeded {
+            span: Span::new(lines.number, base_indent + 1),
+            max_depth: opts.depth_limit(),
+        });
+    }
+    let Some((first, rest)) = segments.split_first() else {
+        let block = Reader::block(*lines, base_indent, depth, opts.clone());
+        return build(block, opts, lines.input.len()).map(Some);
+    };
+
+    // Cursor at the matching entry, and the indent of its children if any
+    let mut found: Option<(Lines, Option<usize>)> = None;
+    // Keys of the block so far, only kept for `DuplicateKeys::Error`
+    let mut seen = HashSet::new();
+    // Whether the entry for `key` is the one to read, given whether a match
+    // was already found: only the first one is for `DuplicateKeys::First`
+    let mut pick = |key: &str, span: Span, matched: bool| -> Result<bool, ToonError> {
+        let duplicates = opts.duplicate_keys;
+        duplicates
+            .check(key, |key| !seen.insert(key.to_string()))
+            .map_err(|e| e.located(span))?;
+        let kept = matched && duplicates == DuplicateKeys::First;
+        Ok(first.matches_key(key) && !kept)
+    };
+    while let Some(line) = lines.peek() {
+        if line.trim().is_empty() {
+            lines.advance();
+            continue;
+        }
+        let indent = indent_of(line);
+        if indent < base_indent {
+            break;
+        }
+        let entry = *lines;
+        let span = Span::new(lines.number, indent + 1);
+        lines.advance();
+        let Some((key_part, val_part)) = line.trim().split_once(':') else {
+            continue;
+        };
+
+        if let Some(header) = parse_array_header(key_part, opts.delimiter) {
+            // A root table (`[N]{...}:`) is indexed directly
+            let root_table = depth == 0 && header.key.is_empty();
+            if pick(header.key, span, found.is_some())? || root_table {
+                found = Some((entry, None));
+            }
+            if header.fields.is_some() {
+                lines.skip_block(indent + 1);
             }
+            continue;
         }
-        ToonValue::Array(items) => {
-            let mut result = String::with_capacity(items.len() * 10);
-            result.push('[');
-            for (i, v) in items.iter().enumerate() {
-                if i > 0 {
-                    result.push_str(", ");
+
+        let children = match lines.peek() {
+            Some(next) if val_part.trim().is_empty() && !next.trim().is_empty() => {
+                Some(indent_of(next)).filter(|&next_indent| next_indent > indent)
+            }
+            _ => None,
+        };
+        if pick(key_part.trim(), span, found.is_some())? {
+            found = Some((entry, children));
+        }
+        if let Some(child_indent) = children {
+            lines.skip_block(child_indent);
+        }
+    }
+
+    let Some((mut lines, children)) = found else {
+        return Ok(None);
+    };
+    let line = lines.peek().unwrap_or_default();
+    let indent = indent_of(line);
+    let Some((key_part, val_part)) = line.trim().split_once(':') else {
+        return Ok(None);
+    };
+
+    if let Some(header) = parse_array_header(key_part, opts.delimiter) {
+        let (rest, array_depth) = if depth == 0 && header.key.is_empty() {
+            (segments, depth)
+        } else {
+            (rest, depth + 1)
+        };
+        return match (&header.fields, rest.split_first()) {
+            // Only the requested row of a table is parsed
+            (Some(fields), Some((PathSegment::Index(idx), rest))) => {
+                lines.advance();
+                let mut skipped = 0;
+                while let Some(row) = lines.peek() {
+                    if !row.trim().is_empty() {
+                        if indent_of(row) <= indent {
+                            break;
+                        }
+                        if skipped == *idx {
+                            let row = parse_row(row, fields, header.delimiter, lines.number, opts)?;
+                            return Ok(select(row, rest));
+                        }
+                        skipped += 1;
+                    }
+                    lines.advance();
                 }
-                result.push_str(&value_to_string(v));
+                Ok(None)
             }
-            result.push(']');
-            result
+            _ => {
+                let array = Reader::array(lines, array_depth, opts.clone());
+                let items = build(array, opts, lines.input.len())?;
+                Ok(select(items, rest))
+            }
+        };
+    }
+
+    lines.advance();
+    match children {
+        Some(child_indent) => find_in_block(&mut lines, child_indent, rest, depth + 1, opts),
+        None if val_part.trim().is_empty() => Ok(select(ToonValue::Map(ToonMap::new()), rest)),
+        None => {
+            let at = Span::within(lines.number - 1, line, val_part.trim_start());
+            let value = parse_value_with(val_part.trim(), at, opts.delimiter, depth + 1, opts)?;
+            Ok(select(value, rest))
         }
-        ToonValue::Map(_) => "[Object]".to_string(),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Follows `segments` into an already parsed value.
+fn select(mut value: ToonValue, segments: &[PathSegment]) -> Option<ToonValue> {
+    for segment in segments {
+        value = match (value, segment) {
+            (ToonValue::Map(mut entries), segment) => {
+                let idx = segment.find_in(&entries)?;
+                entries.swap_remove_index(idx)?.1
+            }
+            (ToonValue::Array(mut items), PathSegment::Index(idx)) if *idx < items.len() => {
+                items.swap_remove(*idx)
+            }
+            _ => return None,
+        };
+    }
+    Some(value)
+}
 
-    // ============================================================================
-    // SECTION 1: BASIC PARSING TESTS
-    // ============================================================================
+/// Pulls the rows of one tabular array out of a document line by line, so
+/// only the current row is ever held in memory. `path` is the path of the
+/// array (see [`split_path`]), or `""` for a root array. In strict mode a
+/// table with another number of rows than its header declares fails once
+/// its last row has been read.
+pub struct RowReader<R> {
+    input: R,
+    path: String,
+    target: Vec<PathSegment>,
+    opts: ParseOptions,
+    line: String,
+    line_no: usize,
+    table: Option<Table>,
+    done: bool,
+}
 
-    #[test]
-    fn test_decode_simple() {
-        let input = "user:\n  id: 123\n  email: ada@example.com";
-        let val = parse(input).unwrap();
+#[derive(Debug)]
+struct Table {
+    indent: usize,
+    fields: Vec<Cow<'static, str>>,
+    delimiter: Delimiter,
+    /// Number of rows declared by the header, and read so far.
+    len: Option<usize>,
+    rows: usize,
+    span: Span,
+}
 
-        if let ToonValue::Map(m) = val {
-            assert_eq!(m.len(), 1);
-            assert_eq!(m[0].0, "user");
-            if let ToonValue::Map(user) = &m[0].1 {
-                assert_eq!(user[0].0, "id");
-                assert_eq!(user[0].1, ToonValue::Int(123));
-                assert_eq!(user[1].0, "email");
-                assert_eq!(user[1].1, ToonValue::String("ada@example.com".to_string()));
-            } else {
-                panic!("Expected user map");
-            }
-        } else {
-            panic!("Expected map");
-        }
+impl<R: io::BufRead> RowReader<R> {
+    /// Fails for a malformed `path`.
+    pub fn new(input: R, path: &str, opts: ParseOptions) -> Result<Self, ToonError> {
+        Ok(Self {
+            input,
+            path: path.to_string(),
+            target: split_path(path)?,
+            opts,
+            line: String::new(),
+            line_no: 0,
+            table: None,
+            done: false,
+        })
     }
 
-    #[test]
-    fn test_parse_primitives() {
-        assert_eq!(parse_value("null"), ToonValue::Null);
-        assert_eq!(parse_value("true"), ToonValue::Bool(true));
-        assert_eq!(parse_value("false"), ToonValue::Bool(false));
-        assert_eq!(parse_value("42"), ToonValue::Int(42));
-        assert_eq!(parse_value("-99"), ToonValue::Int(-99));
-        assert_eq!(parse_value("3.14"), ToonValue::Float(3.14));
-        assert_eq!(parse_value("hello"), ToonValue::String("hello".to_string()));
+    /// Reads the next line into `self.line`; `false` at end of input.
+    fn next_line(&mut self) -> Result<bool, ToonError> {
+        self.line.clear();
+        self.line_no += 1;
+        let read = self
+            .input
+            .read_line(&mut self.line)
+            .map_err(|source| ToonError::Io {
+                span: Span::new(self.line_no, 0),
+                source,
+            })?;
+        Ok(read > 0)
     }
 
-    #[test]
-    fn test_parse_quoted_strings() {
-        assert_eq!(
-            parse_value("\"hello world\""),
-            ToonValue::String("hello world".to_string())
+    /// Skips ahead to the header of the target array.
+    fn find_table(&mut self) -> Result<Table, ToonError> {
+        // Keys of the maps enclosing the current line, with their indent
+        let mut parents: Vec<(usize, String)> = Vec::new();
+        // Indent of an array header whose items are being skipped
+        let mut skipping: Option<usize> = None;
+
+        while self.next_line()? {
+            let trimmed = self.line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let indent = self.line.len() - self.line.trim_start().len();
+            if skipping.is_some_and(|header| indent > header) {
+                continue;
+            }
+            skipping = None;
+            while parents.last().is_some_and(|(parent, _)| *parent >= indent) {
+                parents.pop();
+            }
+
+            let Some((key_part, val_part)) = trimmed.split_once(':') else {
+                continue;
+            };
+            if let Some(header) = parse_array_header(key_part, self.opts.delimiter) {
+                let mut path: Vec<&str> = parents.iter().map(|(_, key)| key.as_str()).collect();
+                if !header.key.is_empty() {
+                    path.push(header.key);
+                }
+                let found = path.len() == self.target.len()
+                    && path
+                        .iter()
+                        .zip(&self.target)
+                        .all(|(key, segment)| segment.matches_key(key));
+                if let (true, Some(fields)) = (found, header.fields) {
+                    return Ok(Table {
+                        indent,
+                        fields: fields
+                            .into_iter()
+                            .map(|field| Cow::Owned(field.into_owned()))
+                            .collect(),
+                        delimiter: header.delimiter,
+                        len: header.len,
+                        rows: 0,
+                        span: Span::new(self.line_no, indent + 1),
+                    });
+                }
+                skipping = Some(indent);
+            } else if val_part.trim().is_empty() {
+                parents.push((indent, key_part.trim().to_string()));
+            }
+        }
+
+        Err(ToonError::TableNotFound {
+            span: Span::new(self.line_no, 0),
+            path: self.path.clone(),
+        })
+    }
+
+    fn next_row(&mut self) -> Result<Option<ToonValue>, ToonError> {
+        if self.table.is_none() {
+            self.table = Some(self.find_table()?);
+        }
+        while self.next_line()? {
+            if self.line.trim().is_empty() {
+                continue;
+            }
+            let Some(table) = &mut self.table else {
+                break;
+            };
+            if self.line.len() - self.line.trim_start().len() <= table.indent {
+                break;
+            }
+            table.rows += 1;
+            return parse_row(
+                &self.line,
+                &table.fields,
+                table.delimiter,
+                self.line_no,
+                &self.opts,
+            )
+            .map(Some);
+        }
+
+        if let Some(table) = &self.table {
+            if let Some(len) = table
+                .len
+                .filter(|len| self.opts.strict && table.rows != *len)
+            {
+                return Err(ToonError::CountMismatch {
+                    span: table.span,
+                    kind: CountKind::Rows,
+                    expected: len,
+                    found: table.rows,
+                });
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<R: io::BufRead> Iterator for RowReader<R> {
+    type Item = Result<ToonValue, ToonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let row = self.next_row().transpose();
+        if !matches!(row, Some(Ok(_))) {
+            self.done = true;
+        }
+        row
+    }
+}
+
+/// The text of an inline value, read in one pass over its bytes. Lists
+/// nested in it are parts of the same text, so their brackets are matched
+/// once up front and each level only looks at its own bytes, jumping over
+/// quoted strings and bracketed groups.
+struct Inline<'a> {
+    text: &'a str,
+    /// Where `text` starts in the document.
+    at: Span,
+    /// Offset of the `]` closing the `[` at each offset, `usize::MAX` for an
+    /// unmatched one. Left empty when the text has no `[`.
+    closing: Vec<usize>,
+}
+
+impl<'a> Inline<'a> {
+    fn new(text: &'a str, at: Span) -> Self {
+        let bytes = text.as_bytes();
+        let mut closing = Vec::new();
+        if bytes.contains(&b'[') {
+            closing = vec![usize::MAX; bytes.len()];
+            let mut open = Vec::new();
+            let mut i = 0;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 1,
+                    b'"' => match closing_quote(bytes, i, bytes.len()) {
+                        Some(end) => i = end,
+                        None => break,
+                    },
+                    b'[' => open.push(i),
+                    b']' => {
+                        if let Some(start) = open.pop() {
+                            closing[start] = i;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+        }
+        Self { text, at, closing }
+    }
+
+    /// Trimmed spans of the parts of `start..end` separated by `delimiter`
+    /// outside quotes and brackets, and whether there was such a delimiter.
+    /// An unterminated quote or unmatched `[` runs to the end of the text. A
+    /// blank last part is dropped, so a blank text has no parts.
+    fn scan(&self, start: usize, end: usize, delimiter: Delimiter) -> (Vec<(usize, usize)>, bool) {
+        let bytes = self.text.as_bytes();
+        let delimiter = delimiter.as_char() as u8;
+        let mut parts = Vec::new();
+        let mut part = start;
+        let mut i = start;
+        while i < end {
+            match bytes[i] {
+                b'\\' => i += 1,
+                b'"' => match closing_quote(bytes, i, end) {
+                    Some(quote) => i = quote,
+                    None => break,
+                },
+                b'[' => match self.closing.get(i) {
+                    Some(&close) if close < end => i = close,
+                    _ => break,
+                },
+                b if b == delimiter => {
+                    parts.push(self.trim(part, i));
+                    part = i + 1;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        let split = !parts.is_empty();
+        let last = self.trim(part, end);
+        if last.0 < last.1 {
+            parts.push(last);
+        }
+        (parts, split)
+    }
+
+    fn parts(&self, start: usize, end: usize, delimiter: Delimiter) -> Vec<(usize, usize)> {
+        self.scan(start, end, delimiter).0
+    }
+
+    fn trim(&self, start: usize, end: usize) -> (usize, usize) {
+        let s = &self.text[start..end];
+        let trimmed = s.trim_start();
+        let start = end - trimmed.len();
+        (start, start + trimmed.trim_end().len())
+    }
+
+    /// Events of the value in `start..end`: a list when it has a delimiter
+    /// outside quotes and brackets, else a quoted string, a list enclosed in
+    /// brackets or a scalar.
+    fn emit(
+        &self,
+        start: usize,
+        end: usize,
+        delimiter: Delimiter,
+        depth: usize,
+        opts: &ParseOptions,
+        out: &mut VecDeque<(Event<'a>, Span)>,
+    ) -> Result<(), ToonError> {
+        let (start, end) = self.trim(start, end);
+        let (parts, split) = self.scan(start, end, delimiter);
+        if split {
+            return self.list(start, end, &parts, delimiter, depth, opts, out);
+        }
+
+        let text = self.text;
+        let s = &text[start..end];
+        if opts.strict && s.starts_with('"') {
+            if closing_quote(text.as_bytes(), start, end).is_none() {
+                let span = self.at.advance(start);
+                return Err(ToonError::UnterminatedString { span });
+            }
+            if let Some((offset, escape)) = invalid_escape(s) {
+                let span = self.at.advance(start + offset);
+                return Err(ToonError::InvalidEscape { span, escape });
+            }
+        }
+        let span = self.at.advance(start);
+        if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+            let value = Scalar::Str(unescape(&s[1..s.len() - 1]));
+            out.push_back((Event::Scalar(value), span));
+            return Ok(());
+        }
+        if end > start && self.closing.get(start) == Some(&(end - 1)) {
+            let parts = self.parts(start + 1, end - 1, delimiter);
+            return self.list(start, end, &parts, delimiter, depth, opts, out);
+        }
+        out.push_back((Event::Scalar(scalar(s)), span));
+        Ok(())
+    }
+
+    /// Events of the list in `start..end` made of `parts`.
+    #[allow(clippy::too_many_arguments)]
+    fn list(
+        &self,
+        start: usize,
+        end: usize,
+        parts: &[(usize, usize)],
+        delimiter: Delimiter,
+        depth: usize,
+        opts: &ParseOptions,
+        out: &mut VecDeque<(Event<'a>, Span)>,
+    ) -> Result<(), ToonError> {
+        let span = self.at.advance(start);
+        if depth > opts.depth_limit() {
+            return Err(ToonError::DepthExceeded {
+                span,
+                max_depth: opts.depth_limit(),
+            });
+        }
+        let open = Event::StartArray {
+            len: Some(parts.len()),
+            fields: None,
+            delimiter,
+        };
+        out.push_back((open, span));
+        for &(start, end) in parts {
+            self.emit(start, end, delimiter, depth + 1, opts, out)?;
+        }
+        out.push_back((Event::End, self.at.advance(end)));
+        Ok(())
+    }
+}
+
+/// Offset of the `"` closing the one at `start`, skipping escaped ones.
+fn closing_quote(bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < end {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn scalar(s: &str) -> Scalar<'_> {
+    match s {
+        "true" => return Scalar::Bool(true),
+        "false" => return Scalar::Bool(false),
+        "null" => return Scalar::Null,
+        _ => {}
+    }
+    // Only these can start a number, `inf` or `NaN`
+    if matches!(
+        s.as_bytes().first(),
+        Some(b'0'..=b'9' | b'+' | b'-' | b'.' | b'i' | b'I' | b'n' | b'N')
+    ) {
+        if let Ok(i) = s.parse() {
+            return Scalar::Int(i);
+        }
+        if let Ok(f) = s.parse() {
+            return Scalar::Float(f);
+        }
+    }
+    Scalar::Str(Cow::Borrowed(s))
+}
+
+/// Offset and character of the first escape in `s` other than those written
+/// by the encoder.
+fn invalid_escape(s: &str) -> Option<(usize, char)> {
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some((_, '"' | 'n' | '\\')) => {}
+                Some((_, other)) => return Some((i, other)),
+                None => return Some((i, '\\')),
+            }
+        }
+    }
+    None
+}
+
+/// Undoes the escapes written by the encoder (`\"`, `\n`, `\\`); any other
+/// backslash is kept as is.
+fn unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// The text of a quoted string, unescaped, or `s` itself.
+fn unquote(s: &str) -> Cow<'_, str> {
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) => unescape(inner),
+        None => Cow::Borrowed(s),
+    }
+}
+
+fn parse_value_with(
+    s: &str,
+    at: Span,
+    delimiter: Delimiter,
+    depth: usize,
+    opts: &ParseOptions,
+) -> Result<ToonValue, ToonError> {
+    let mut events = VecDeque::new();
+    emit_value(s, at, delimiter, depth, opts, &mut events)?;
+    build(events.into_iter().map(Ok), opts, s.len())
+}
+
+/// Events of an inline value at `depth`; `at` is where `s` starts.
+fn emit_value<'a>(
+    s: &'a str,
+    at: Span,
+    delimiter: Delimiter,
+    depth: usize,
+    opts: &ParseOptions,
+    out: &mut VecDeque<(Event<'a>, Span)>,
+) -> Result<(), ToonError> {
+    Inline::new(s, at).emit(0, s.len(), delimiter, depth, opts, out)
+}
+
+// --- Encoder ---
+
+/// Nesting level past which the encoder writes `[MaxDepthExceeded]` when no
+/// `max_depth` is given.
+pub const MAX_ENCODE_DEPTH: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodeOptions {
+    /// Spaces added per nesting level.
+    pub indent: usize,
+    pub delimiter: Delimiter,
+    /// Write tabular headers as `key[]{...}:`, without the row count, so rows
+    /// can be written before their number is known.
+    pub lengthless: bool,
+    /// Nesting level past which `[MaxDepthExceeded]` is written instead of
+    /// the value. Capped at [`MAX_DEPTH_LIMIT`].
+    pub max_depth: usize,
+}
+
+impl EncodeOptions {
+    /// `max_depth`, capped at [`MAX_DEPTH_LIMIT`].
+    pub fn depth_limit(&self) -> usize {
+        self.max_depth.min(MAX_DEPTH_LIMIT)
+    }
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            delimiter: Delimiter::default(),
+            lengthless: false,
+            max_depth: MAX_ENCODE_DEPTH,
+        }
+    }
+}
+
+pub fn encode(val: &ToonValue) -> String {
+    encode_with(val, &EncodeOptions::default())
+}
+
+pub fn encode_with(val: &ToonValue, opts: &EncodeOptions) -> String {
+    let mut out = String::new();
+    // Writing into a `String` cannot fail
+    let _ = encode_recursive_impl(val, 0, &mut out, 0, opts);
+    out.truncate(out.trim_end().len());
+    out
+}
+
+/// Streams the encoded document into `writer` as it is produced. The output
+/// is byte-for-byte the same as [`encode_with`].
+pub fn encode_to_writer<W: io::Write>(
+    val: &ToonValue,
+    opts: &EncodeOptions,
+    writer: W,
+) -> io::Result<()> {
+    let mut encoder = StreamEncoder::new(writer, opts.clone());
+    encoder.value(val)?;
+    encoder.finish()
+}
+
+/// Writes a document piece by piece: whole values, top-level map entries,
+/// or tabular arrays one row at a time. Trailing whitespace is held back
+/// until more content follows, so the output matches [`encode_with`].
+pub struct StreamEncoder<W> {
+    out: TrimEndWriter<W>,
+    opts: EncodeOptions,
+}
+
+impl<W: io::Write> StreamEncoder<W> {
+    pub fn new(writer: W, opts: EncodeOptions) -> Self {
+        Self {
+            out: TrimEndWriter {
+                inner: writer,
+                pending: String::new(),
+                error: None,
+            },
+            opts,
+        }
+    }
+
+    /// Writes a complete value as the whole document.
+    pub fn value(&mut self, val: &ToonValue) -> io::Result<()> {
+        let result = encode_recursive_impl(val, 0, &mut self.out, 0, &self.opts);
+        self.check(result)
+    }
+
+    /// Writes the whole document with `f`, for callers encoding something
+    /// other than a `ToonValue` straight into the output.
+    pub fn write_with(
+        &mut self,
+        f: impl FnOnce(&mut dyn fmt::Write) -> fmt::Result,
+    ) -> io::Result<()> {
+        let result = f(&mut self.out);
+        self.check(result)
+    }
+
+    /// Writes one entry of a root map.
+    pub fn entry(&mut self, key: &str, val: &ToonValue) -> io::Result<()> {
+        let result = encode_entry(key, val, 0, &mut self.out, 0, &self.opts);
+        self.check(result)
+    }
+
+    /// Starts a tabular array at the root (`key` empty) or under a root map
+    /// key. `len` is ignored when the options ask for lengthless headers.
+    pub fn table_header(&mut self, key: &str, len: usize, fields: &[&str]) -> io::Result<()> {
+        let result = write_table_header(&mut self.out, key, len, fields, 0, &self.opts);
+        self.check(result)
+    }
+
+    /// Writes one row of the table started by [`Self::table_header`].
+    pub fn table_row<'v>(
+        &mut self,
+        cells: impl IntoIterator<Item = &'v ToonValue>,
+    ) -> io::Result<()> {
+        let result = write_table_row(&mut self.out, cells, self.opts.indent, &self.opts);
+        self.check(result)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.inner.flush()
+    }
+
+    fn check(&mut self, result: fmt::Result) -> io::Result<()> {
+        result.map_err(|fmt::Error| {
+            self.out
+                .error
+                .take()
+                .unwrap_or_else(|| io::Error::other("formatting error"))
+        })
+    }
+}
+
+/// Adapts an `io::Write` to the encoder, dropping trailing whitespace at the
+/// end of the document.
+struct TrimEndWriter<W> {
+    inner: W,
+    pending: String,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for TrimEndWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let content = s.trim_end();
+        if !content.is_empty() {
+            let result = self
+                .inner
+                .write_all(self.pending.as_bytes())
+                .and_then(|()| self.inner.write_all(content.as_bytes()));
+            if let Err(e) = result {
+                self.error = Some(e);
+                return Err(fmt::Error);
+            }
+            self.pending.clear();
+        }
+        self.pending.push_str(&s[content.len()..]);
+        Ok(())
+    }
+}
+
+pub(crate) fn encode_recursive_impl<W: fmt::Write>(
+    val: &ToonValue,
+    indent: usize,
+    out: &mut W,
+    depth: usize,
+    opts: &EncodeOptions,
+) -> fmt::Result {
+    if depth > opts.depth_limit() {
+        return out.write_str("[MaxDepthExceeded]");
+    }
+
+    match val {
+        ToonValue::Map(entries) => {
+            for (key, value) in entries {
+                encode_entry(key, value, indent, out, depth, opts)?;
+            }
+        }
+        // Only reached at the root: arrays under a key go through `encode_entry`
+        ToonValue::Array(items) if contains_map(items) => {
+            encode_array_block("", items, indent, out, depth, opts)?;
+        }
+        ToonValue::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                if i > 0 {
+                    out.write_str(opts.delimiter.separator())?;
+                }
+                out.write_str(&value_to_string(v, opts.delimiter))?;
+            }
+            out.write_char('\n')?;
+        }
+        _ => {
+            out.write_str(&value_to_string(val, opts.delimiter))?;
+            out.write_char('\n')?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn encode_entry<W: fmt::Write>(
+    key: &str,
+    value: &ToonValue,
+    indent: usize,
+    out: &mut W,
+    depth: usize,
+    opts: &EncodeOptions,
+) -> fmt::Result {
+    match value {
+        ToonValue::Map(_) => {
+            write_indent(out, indent)?;
+            out.write_str(key)?;
+            out.write_str(":\n")?;
+            encode_recursive_impl(value, indent + opts.indent, out, depth + 1, opts)
+        }
+        ToonValue::Array(items) if contains_map(items) => {
+            encode_array_block(key, items, indent, out, depth + 1, opts)
+        }
+        _ => {
+            write_indent(out, indent)?;
+            out.write_str(key)?;
+            out.write_str(": ")?;
+            encode_recursive_impl(value, 0, out, depth + 1, opts) // 0 indent because it's inline
+        }
+    }
+}
+
+/// Writes an array of maps, as a table when the rows share their fields and
+/// only hold primitives. `key` is empty for a root array.
+fn encode_array_block<W: fmt::Write>(
+    key: &str,
+    items: &[ToonValue],
+    indent: usize,
+    out: &mut W,
+    depth: usize,
+    opts: &EncodeOptions,
+) -> fmt::Result {
+    if let Some(fields) = table_fields(items) {
+        write_table_header(out, key, items.len(), &fields, indent, opts)?;
+        // Rows sit one level below their header, even at the root
+        let row_indent = indent + opts.indent;
+        for item in items {
+            if let ToonValue::Map(entries) = item {
+                write_table_row(out, entries.iter().map(|(_, v)| v), row_indent, opts)?;
+            }
+        }
+        return Ok(());
+    }
+
+    // No inline form for lists holding maps: write them keyed by index
+    let item_indent = if key.is_empty() {
+        indent
+    } else {
+        write_indent(out, indent)?;
+        out.write_str(key)?;
+        out.write_str(":\n")?;
+        indent + opts.indent
+    };
+    for (i, item) in items.iter().enumerate() {
+        encode_entry(&i.to_string(), item, item_indent, out, depth, opts)?;
+    }
+    Ok(())
+}
+
+fn contains_map(items: &[ToonValue]) -> bool {
+    items.iter().any(|item| matches!(item, ToonValue::Map(_)))
+}
+
+/// The shared field names of `items`, if every item is a non-empty map with
+/// the same keys in the same order and only primitive values.
+fn table_fields(items: &[ToonValue]) -> Option<Vec<&str>> {
+    let ToonValue::Map(first) = items.first()? else {
+        return None;
+    };
+    if first.is_empty() {
+        return None;
+    }
+    let uniform = items.iter().all(|item| match item {
+        ToonValue::Map(entries) => {
+            entries.len() == first.len()
+                && entries.iter().zip(first).all(|((key, value), (field, _))| {
+                    key == field && !matches!(value, ToonValue::Array(_) | ToonValue::Map(_))
+                })
+        }
+        _ => false,
+    });
+    uniform.then(|| first.iter().map(|(key, _)| key.as_str()).collect())
+}
+
+fn write_table_header<W: fmt::Write>(
+    out: &mut W,
+    key: &str,
+    len: usize,
+    fields: &[&str],
+    indent: usize,
+    opts: &EncodeOptions,
+) -> fmt::Result {
+    write_indent(out, indent)?;
+    out.write_str(key)?;
+    out.write_char('[')?;
+    if !opts.lengthless {
+        write!(out, "{len}")?;
+    }
+    // Non-default delimiters are declared in the header so readers need no
+    // configuration
+    if opts.delimiter != Delimiter::Comma {
+        out.write_char(opts.delimiter.as_char())?;
+    }
+    out.write_str("]{")?;
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.write_char(opts.delimiter.as_char())?;
+        }
+        out.write_str(&value_to_string(
+            &ToonValue::String(field.to_string()),
+            opts.delimiter,
+        ))?;
+    }
+    out.write_str("}:\n")
+}
+
+fn write_table_row<'v, W: fmt::Write>(
+    out: &mut W,
+    cells: impl IntoIterator<Item = &'v ToonValue>,
+    indent: usize,
+    opts: &EncodeOptions,
+) -> fmt::Result {
+    write_indent(out, indent)?;
+    for (i, cell) in cells.into_iter().enumerate() {
+        if i > 0 {
+            out.write_char(opts.delimiter.as_char())?;
+        }
+        out.write_str(&value_to_string(cell, opts.delimiter))?;
+    }
+    out.write_char('\n')
+}
+
+pub(crate) fn write_indent<W: fmt::Write>(out: &mut W, indent: usize) -> fmt::Result {
+    for _ in 0..indent {
+        out.write_char(' ')?;
+    }
+    Ok(())
+}
+
+pub(crate) fn value_to_string(val: &ToonValue, delimiter: Delimiter) -> String {
+    match val {
+        ToonValue::Null => "null".to_string(),
+        ToonValue::Bool(b) => b.to_string(),
+        ToonValue::Int(i) => i.to_string(),
+        ToonValue::Float(f) => f.to_string(),
+        ToonValue::String(s) => {
+            let mut result = String::with_capacity(s.len() + 2);
+            // Writing into a `String` cannot fail
+            let _ = write_string(&mut result, s, delimiter);
+            result
+        }
+        ToonValue::Array(items) => {
+            let mut result = String::with_capacity(items.len() * 10);
+            result.push('[');
+            for (i, v) in items.iter().enumerate() {
+                if i > 0 {
+                    result.push_str(delimiter.separator());
+                }
+                result.push_str(&value_to_string(v, delimiter));
+            }
+            result.push(']');
+            result
+        }
+        ToonValue::Map(_) => "[Object]".to_string(),
+    }
+}
+
+/// Writes a string value, quoted and escaped when it would otherwise be
+/// read back as something else, such as `null`, a boolean or a number.
+pub(crate) fn write_string<W: fmt::Write>(
+    out: &mut W,
+    s: &str,
+    delimiter: Delimiter,
+) -> fmt::Result {
+    // Always quote empty strings
+    if s.is_empty() {
+        return out.write_str("\"\"");
+    }
+    // Quote if contains special chars
+    if s.contains('\n')
+        || s.contains(':')
+        || s.contains(',')
+        || s.contains(delimiter.as_char())
+        || s.contains('"')
+        || s.trim() != s
+        || (s.starts_with('[') && s.ends_with(']'))
+        || !matches!(scalar(s), Scalar::Str(_))
+    {
+        // Simple escape
+        out.write_char('"')?;
+        for c in s.chars() {
+            match c {
+                '\\' => out.write_str("\\\\")?,
+                '"' => out.write_str("\\\"")?,
+                '\n' => out.write_str("\\n")?,
+                c => out.write_char(c)?,
+            }
+        }
+        out.write_char('"')
+    } else {
+        out.write_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_value(s: &str) -> ToonValue {
+        parse_value_with(
+            s,
+            Span::new(1, 1),
+            Delimiter::Comma,
+            0,
+            &ParseOptions::default(),
+        )
+        .unwrap()
+    }
+
+    // ============================================================================
+    // SECTION 1: BASIC PARSING TESTS
+    // ============================================================================
+
+    #[test]
+    fn test_decode_simple() {
+        let input = "user:\n  id: 123\n  email: ada@example.com";
+        let val = parse(input).unwrap();
+
+        if let ToonValue::Map(m) = val {
+            assert_eq!(m.len(), 1);
+            assert_eq!(m.get_index(0).unwrap().0, "user");
+            if let ToonValue::Map(user) = &m[0] {
+                assert_eq!(user.get_index(0).unwrap().0, "id");
+                assert_eq!(user[0], ToonValue::Int(123));
+                assert_eq!(user.get_index(1).unwrap().0, "email");
+                assert_eq!(user[1], ToonValue::String("ada@example.com".to_string()));
+            } else {
+                panic!("Expected user map");
+            }
+        } else {
+            panic!("Expected map");
+        }
+    }
+
+    #[test]
+    fn test_parse_primitives() {
+        assert_eq!(parse_value("null"), ToonValue::Null);
+        assert_eq!(parse_value("true"), ToonValue::Bool(true));
+        assert_eq!(parse_value("false"), ToonValue::Bool(false));
+        assert_eq!(parse_value("42"), ToonValue::Int(42));
+        assert_eq!(parse_value("-99"), ToonValue::Int(-99));
+        assert_eq!(parse_value("3.14"), ToonValue::Float(3.14));
+        assert_eq!(parse_value("hello"), ToonValue::String("hello".to_string()));
+    }
+
+    #[test]
+    fn test_parse_quoted_strings() {
+        assert_eq!(
+            parse_value("\"hello world\""),
+            ToonValue::String("hello world".to_string())
+        );
+        assert_eq!(
+            parse_value("\"string with \\\"quotes\\\"\""),
+            ToonValue::String("string with \"quotes\"".to_string())
+        );
+        assert_eq!(
+            parse_value("\"path\\\\to\\\\file\""),
+            ToonValue::String("path\\to\\file".to_string())
+        );
+        assert_eq!(
+            parse_value("\"Line 1\\nLine 2\""),
+            ToonValue::String("Line 1\nLine 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_inline_arrays() {
+        // Simple numeric array
+        let result = parse_value("1, 2, 3, 4, 5");
+        if let ToonValue::Array(items) = result {
+            assert_eq!(items.len(), 5);
+            assert_eq!(items[0], ToonValue::Int(1));
+            assert_eq!(items[4], ToonValue::Int(5));
+        } else {
+            panic!("Expected array");
+        }
+
+        // Mixed types
+        let result = parse_value("42, hello, true");
+        if let ToonValue::Array(items) = result {
+            assert_eq!(items.len(), 3);
+            assert_eq!(items[0], ToonValue::Int(42));
+            assert_eq!(items[1], ToonValue::String("hello".to_string()));
+            assert_eq!(items[2], ToonValue::Bool(true));
+        } else {
+            panic!("Expected array");
+        }
+    }
+
+    #[test]
+    fn test_parse_deep_nesting() {
+        let input = "l1:\n  l2:\n    l3:\n      l4:\n        l5: value";
+        let val = parse(input).unwrap();
+
+        // Navigate through nested structure
+        if let ToonValue::Map(m) = val {
+            assert_eq!(m.len(), 1);
+            if let ToonValue::Map(l2) = &m[0] {
+                if let ToonValue::Map(l3) = &l2[0] {
+                    if let ToonValue::Map(l4) = &l3[0] {
+                        if let ToonValue::Map(l5) = &l4[0] {
+                            assert_eq!(l5.get_index(0).unwrap().0, "l5");
+                            assert_eq!(l5[0], ToonValue::String("value".to_string()));
+                        } else {
+                            panic!("Expected l4 to have map");
+                        }
+                    } else {
+                        panic!("Expected l3 to have map");
+                    }
+                } else {
+                    panic!("Expected l2 to have map");
+                }
+            } else {
+                panic!("Expected l1 to have map");
+            }
+        } else {
+            panic!("Expected root map");
+        }
+    }
+
+    #[test]
+    fn test_parse_multiple_keys() {
+        let input = "name: Alice\nage: 30\nemail: alice@example.com";
+        let val = parse(input).unwrap();
+
+        if let ToonValue::Map(m) = val {
+            assert_eq!(m.len(), 3);
+            assert_eq!(m.get_index(0).unwrap().0, "name");
+            assert_eq!(m[0], ToonValue::String("Alice".to_string()));
+            assert_eq!(m.get_index(1).unwrap().0, "age");
+            assert_eq!(m[1], ToonValue::Int(30));
+            assert_eq!(m.get_index(2).unwrap().0, "email");
+            assert_eq!(m[2], ToonValue::String("alice@example.com".to_string()));
+        } else {
+            panic!("Expected map");
+        }
+    }
+
+    // ============================================================================
+    // SECTION 2: ENCODING TESTS
+    // ============================================================================
+
+    #[test]
+    fn test_encode_simple() {
+        let mut user_map = ToonMap::new();
+        user_map.insert("id".to_string(), ToonValue::Int(123));
+        user_map.insert(
+            "email".to_string(),
+            ToonValue::String("ada@example.com".to_string()),
+        );
+
+        let mut root_map = ToonMap::new();
+        root_map.insert("user".to_string(), ToonValue::Map(user_map));
+
+        let val = ToonValue::Map(root_map);
+        let encoded = encode(&val);
+
+        let expected = "user:\n  id: 123\n  email: ada@example.com";
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_encode_primitives() {
+        assert_eq!(encode(&ToonValue::Null), "null");
+        assert_eq!(encode(&ToonValue::Bool(true)), "true");
+        assert_eq!(encode(&ToonValue::Bool(false)), "false");
+        assert_eq!(encode(&ToonValue::Int(42)), "42");
+        assert_eq!(encode(&ToonValue::Int(-99)), "-99");
+        assert_eq!(encode(&ToonValue::Float(3.14)), "3.14");
+        assert_eq!(encode(&ToonValue::String("hello".to_string())), "hello");
+    }
+
+    #[test]
+    fn test_encode_quoted_strings() {
+        // Strings with newlines should be quoted
+        let val = ToonValue::String("Line 1\nLine 2".to_string());
+        let encoded = encode(&val);
+        assert!(encoded.contains('"') && encoded.contains("\\n"));
+
+        // Strings with colons should be quoted
+        let val = ToonValue::String("key: value".to_string());
+        let encoded = encode(&val);
+        assert!(encoded.contains('"'));
+
+        // Strings with quotes should be escaped
+        let val = ToonValue::String("He said \"hello\"".to_string());
+        let encoded = encode(&val);
+        assert!(encoded.contains("\\\""));
+    }
+
+    #[test]
+    fn test_encode_arrays() {
+        let items = vec![ToonValue::Int(1), ToonValue::Int(2), ToonValue::Int(3)];
+        let val = ToonValue::Array(items);
+        let encoded = encode(&val);
+        assert!(encoded.contains("1, 2, 3"));
+    }
+
+    #[test]
+    fn test_encode_mixed_array_types() {
+        let items = vec![
+            ToonValue::Int(42),
+            ToonValue::String("hello".to_string()),
+            ToonValue::Bool(true),
+            ToonValue::Null,
+        ];
+        let val = ToonValue::Array(items);
+        let encoded = encode(&val);
+        assert!(encoded.contains("42"));
+        assert!(encoded.contains("hello"));
+        assert!(encoded.contains("true"));
+        assert!(encoded.contains("null"));
+    }
+
+    // ============================================================================
+    // SECTION 3: ROUNDTRIP TESTS (ENCODE -> DECODE CONSISTENCY)
+    // ============================================================================
+
+    #[test]
+    fn test_roundtrip_simple_map() {
+        let input = "user:\n  id: 123\n  email: ada@example.com";
+        let parsed = parse(input).unwrap();
+        let encoded = encode(&parsed);
+        let reparsed = parse(&encoded).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    fn test_roundtrip_nested_structure() {
+        let input = "company:\n  name: TechCorp\n  departments:\n    engineering:\n      employees: 10\n    sales:\n      employees: 5";
+        let parsed = parse(input).unwrap();
+        let encoded = encode(&parsed);
+        let reparsed = parse(&encoded).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    fn test_roundtrip_arrays() {
+        let items = vec![ToonValue::Int(1), ToonValue::Int(2), ToonValue::Int(3)];
+        let val = ToonValue::Array(items.clone());
+        let encoded = encode(&val);
+        let decoded = parse(&encoded).unwrap();
+        assert_eq!(ToonValue::Array(items), decoded);
+    }
+
+    #[test]
+    fn test_full_example() {
+        let input = "user:\n  id: 123\n  email: ada@example.com\n  metadata:\n    active: true\n    score: 9.5";
+        let val = parse(input).unwrap();
+        let encoded = encode(&val);
+        assert_eq!(encoded, input);
+    }
+
+    // ============================================================================
+    // SECTION 4: EDGE CASES AND SPECIAL CHARACTERS
+    // ============================================================================
+
+    #[test]
+    fn test_empty_string_key() {
+        let mut map = ToonMap::new();
+        map.insert("empty_value".to_string(), ToonValue::String("".to_string()));
+        let val = ToonValue::Map(map);
+        let encoded = encode(&val);
+        assert!(encoded.contains("empty_value:") && encoded.contains("\"\""));
+    }
+
+    #[test]
+    fn test_special_characters_in_string() {
+        let test_strings = vec!["!@#$%^&*()", "path/to/file"];
+
+        for test_str in test_strings {
+            let val = ToonValue::String(test_str.to_string());
+            let encoded = encode(&val);
+            let decoded = parse(&encoded).unwrap();
+
+            if let ToonValue::String(decoded_str) = decoded {
+                assert_eq!(test_str, decoded_str, "Failed roundtrip for: {}", test_str);
+            } else {
+                panic!("Expected string, got {:?}", decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unicode_characters() {
+        let unicode_strings = vec!["Hello 🌍", "你好世界", "مرحبا بالعالم", "Привет мир"];
+
+        for test_str in unicode_strings {
+            let val = ToonValue::String(test_str.to_string());
+            let encoded = encode(&val);
+            let decoded = parse(&encoded).unwrap();
+
+            if let ToonValue::String(decoded_str) = decoded {
+                assert_eq!(test_str, decoded_str, "Failed roundtrip for: {}", test_str);
+            } else {
+                panic!("Expected string, got {:?}", decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_integers() {
+        let large_int = i64::MAX;
+        let val = ToonValue::Int(large_int);
+        let encoded = encode(&val);
+        let decoded = parse(&encoded).unwrap();
+        assert_eq!(val, decoded);
+
+        let neg_large_int = i64::MIN;
+        let val = ToonValue::Int(neg_large_int);
+        let encoded = encode(&val);
+        let decoded = parse(&encoded).unwrap();
+        assert_eq!(val, decoded);
+    }
+
+    #[test]
+    fn test_float_precision() {
+        let float_val = 3.141592653589793;
+        let val = ToonValue::Float(float_val);
+        let encoded = encode(&val);
+        let decoded = parse(&encoded).unwrap();
+        if let ToonValue::Float(f) = decoded {
+            assert!((float_val - f).abs() < 0.0001);
+        } else {
+            panic!("Expected float");
+        }
+    }
+
+    #[test]
+    fn test_parse_empty_input() {
+        let result = parse("");
+        assert_eq!(result.unwrap(), ToonValue::Null);
+    }
+
+    #[test]
+    fn test_map_with_many_entries() {
+        let mut map = ToonMap::new();
+        for i in 1..=50 {
+            map.insert(format!("key{}", i), ToonValue::Int(i as i64));
+        }
+        let val = ToonValue::Map(map);
+        let encoded = encode(&val);
+        let decoded = parse(&encoded).unwrap();
+        assert_eq!(val, decoded);
+    }
+
+    #[test]
+    fn test_deeply_nested_arrays() {
+        let inner = vec![ToonValue::Int(1), ToonValue::Int(2)];
+        let middle = vec![ToonValue::Array(inner)];
+        let outer = vec![ToonValue::Array(middle)];
+        let val = ToonValue::Array(outer);
+
+        let encoded = encode(&val);
+        // Verify nested arrays are encoded correctly
+        assert!(encoded.contains("["));
+    }
+
+    #[test]
+    fn test_encode_max_depth() {
+        let mut val = ToonValue::Int(1);
+        for _ in 0..150 {
+            val = ToonValue::Map(ToonMap::from([("k".to_string(), val)]));
+        }
+
+        assert!(encode(&val).contains("[MaxDepthExceeded]"));
+        let opts = EncodeOptions {
+            max_depth: 200,
+            ..EncodeOptions::default()
+        };
+        let encoded = encode_with(&val, &opts);
+        assert!(!encoded.contains("[MaxDepthExceeded]"));
+        let parse_opts = ParseOptions {
+            max_depth: 200,
+            ..ParseOptions::default()
+        };
+        assert_eq!(parse_with(&encoded, &parse_opts).unwrap(), val);
+    }
+
+    // ============================================================================
+    // SECTION 5: FORMAT OPTIONS
+    // ============================================================================
+
+    #[test]
+    fn test_encode_with_indent_and_delimiter() {
+        let val = ToonValue::Map(ToonMap::from([(
+            "user".to_string(),
+            ToonValue::Map(ToonMap::from([(
+                "tags".to_string(),
+                ToonValue::Array(vec![
+                    ToonValue::String("a,b".to_string()),
+                    ToonValue::String("c".to_string()),
+                ]),
+            )])),
+        )]));
+        let opts = EncodeOptions {
+            indent: 4,
+            delimiter: Delimiter::Pipe,
+            ..EncodeOptions::default()
+        };
+
+        let encoded = encode_with(&val, &opts);
+        assert_eq!(encoded, "user:\n    tags: \"a,b\"|c");
+
+        let parse_opts = ParseOptions {
+            delimiter: Delimiter::Pipe,
+            ..ParseOptions::default()
+        };
+        assert_eq!(parse_with(&encoded, &parse_opts).unwrap(), val);
+    }
+
+    #[test]
+    fn test_tab_delimiter_roundtrip() {
+        let val = ToonValue::Map(ToonMap::from([(
+            "row".to_string(),
+            ToonValue::Array(vec![
+                ToonValue::Int(1),
+                ToonValue::String("x, y".to_string()),
+                ToonValue::Bool(true),
+            ]),
+        )]));
+        let encoded = encode_with(
+            &val,
+            &EncodeOptions {
+                delimiter: Delimiter::Tab,
+                ..EncodeOptions::default()
+            },
         );
+        assert_eq!(encoded, "row: 1\t\"x, y\"\ttrue");
+
+        let decoded = parse_with(
+            &encoded,
+            &ParseOptions {
+                delimiter: Delimiter::Tab,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(decoded, val);
+    }
+
+    #[test]
+    fn test_strict_parsing() {
+        let strict = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+
+        assert!(parse("a: 1\njunk\nb: 2").is_ok());
+        let err = parse_with("a: 1\njunk\nb: 2", &strict).unwrap_err();
+        assert_eq!(err.to_string(), "Line 2: expected 'key: value'");
+
+        let err = parse_with("a: 1\n  b: 2", &strict).unwrap_err();
+        assert_eq!(err.to_string(), "Line 2: unexpected indentation");
+
+        assert!(parse_with("a:\n  b: 2\nc: 3", &strict).is_ok());
+    }
+
+    #[test]
+    fn test_parse_max_depth() {
+        let input = "a:\n  b:\n    c: 1";
+        let shallow = ParseOptions {
+            max_depth: 1,
+            ..ParseOptions::default()
+        };
+        assert!(parse_with(input, &shallow).is_err());
+        assert!(parse_with(input, &ParseOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_encode_to_writer_matches_encode() {
+        let values = vec![
+            ToonValue::Map(ToonMap::from([
+                ("name".to_string(), ToonValue::String("Ada".to_string())),
+                (
+                    "address".to_string(),
+                    ToonValue::Map(ToonMap::from([("city".to_string(), ToonValue::Null)])),
+                ),
+                ("tags".to_string(), ToonValue::Array(vec![])),
+            ])),
+            ToonValue::Map(ToonMap::from([(
+                "empty".to_string(),
+                ToonValue::Map(ToonMap::new()),
+            )])),
+            ToonValue::Array(vec![ToonValue::Int(1), ToonValue::Float(2.5)]),
+            ToonValue::String("  padded  ".to_string()),
+        ];
+        let opts = EncodeOptions {
+            indent: 3,
+            delimiter: Delimiter::Pipe,
+            ..EncodeOptions::default()
+        };
+
+        for val in values {
+            let mut out = Vec::new();
+            encode_to_writer(&val, &opts, &mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), encode_with(&val, &opts));
+        }
+    }
+
+    // ============================================================================
+    // SECTION 6: ARRAY HEADERS AND ROW READER
+    // ============================================================================
+
+    fn row(cells: &[(&str, ToonValue)]) -> ToonValue {
+        ToonValue::Map(
+            cells
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse_tabular_array() {
+        let input = "users[2]{id,name}:\n  1,Ada\n  2,\"Bob, Jr\"\ncount: 2";
+        let expected = ToonValue::Map(ToonMap::from([
+            (
+                "users".to_string(),
+                ToonValue::Array(vec![
+                    row(&[
+                        ("id", ToonValue::Int(1)),
+                        ("name", ToonValue::String("Ada".to_string())),
+                    ]),
+                    row(&[
+                        ("id", ToonValue::Int(2)),
+                        ("name", ToonValue::String("Bob, Jr".to_string())),
+                    ]),
+                ]),
+            ),
+            ("count".to_string(), ToonValue::Int(2)),
+        ]));
+        assert_eq!(parse(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_inline_array_header() {
+        let val = parse("one[1]: x\nnone[0]:\npiped[2|]: a,b|c").unwrap();
         assert_eq!(
-            parse_value("\"string with \\\"quotes\\\"\""),
-            ToonValue::String("string with \"quotes\"".to_string())
+            val,
+            ToonValue::Map(ToonMap::from([
+                (
+                    "one".to_string(),
+                    ToonValue::Array(vec![ToonValue::String("x".to_string())])
+                ),
+                ("none".to_string(), ToonValue::Array(vec![])),
+                (
+                    "piped".to_string(),
+                    ToonValue::Array(vec![
+                        ToonValue::String("a,b".to_string()),
+                        ToonValue::String("c".to_string()),
+                    ])
+                ),
+            ]))
         );
+    }
+
+    #[test]
+    fn test_parse_root_tabular_array() {
+        let val = parse("[2]{a}:\n  1\n  2").unwrap();
         assert_eq!(
-            parse_value("\"path\\\\to\\\\file\""),
-            ToonValue::String("path\\to\\file".to_string())
+            val,
+            ToonValue::Array(vec![
+                row(&[("a", ToonValue::Int(1))]),
+                row(&[("a", ToonValue::Int(2))]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_strict_tabular_length() {
+        let strict = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+        let err = parse_with("rows[3]{a}:\n  1\n  2", &strict).unwrap_err();
+        assert_eq!(err.to_string(), "Line 1: expected 3 rows, found 2");
+        assert!(parse("rows[3]{a}:\n  1\n  2").is_ok());
+        assert!(parse("rows[1]{a,b}:\n  1").is_err());
+    }
+
+    #[test]
+    fn test_row_reader() {
+        let input = "meta:\n  rows[9]{x}:\n    0\ndata:\n  other[1]{id}:\n    9\n  users[2]{id,name}:\n    1,Ada\n\n    2,Bob\n  after: true\n";
+        let rows: Vec<_> = RowReader::new(input.as_bytes(), "data.users", ParseOptions::default())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                row(&[
+                    ("id", ToonValue::Int(1)),
+                    ("name", ToonValue::String("Ada".to_string())),
+                ]),
+                row(&[
+                    ("id", ToonValue::Int(2)),
+                    ("name", ToonValue::String("Bob".to_string())),
+                ]),
+            ]
         );
+
+        let mut missing =
+            RowReader::new(input.as_bytes(), "users", ParseOptions::default()).unwrap();
         assert_eq!(
-            parse_value("\"Line 1\\nLine 2\""),
-            ToonValue::String("Line 1\nLine 2".to_string())
+            missing.next().unwrap().unwrap_err().to_string(),
+            "No tabular array at path 'users'"
         );
+        assert!(missing.next().is_none());
     }
 
     #[test]
-    fn test_parse_inline_arrays() {
-        // Simple numeric array
-        let result = parse_value("1, 2, 3, 4, 5");
-        if let ToonValue::Array(items) = result {
-            assert_eq!(items.len(), 5);
-            assert_eq!(items[0], ToonValue::Int(1));
-            assert_eq!(items[4], ToonValue::Int(5));
-        } else {
-            panic!("Expected array");
-        }
+    fn test_row_reader_paths_and_counts() {
+        let input = "users:\n  0:\n    orders[2]{id}:\n      7\n  v1.2:\n    rows[1]{a}:\n      x";
+        let opts = ParseOptions::default();
+        let read = |path| -> Vec<ToonValue> {
+            RowReader::new(input.as_bytes(), path, ParseOptions::default())
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            read("users[0].orders"),
+            vec![row(&[("id", ToonValue::Int(7))])]
+        );
+        assert_eq!(read("users.0.orders").len(), 1);
+        assert_eq!(read("users.\"v1.2\".rows").len(), 1);
+        assert!(RowReader::new(input.as_bytes(), "users[x]", opts.clone()).is_err());
+
+        // Strict mode checks the declared count once the table ends
+        let strict = ParseOptions {
+            strict: true,
+            ..opts
+        };
+        let short = "r[5]{a}:\n  1\n  2";
+        let mut rows = RowReader::new(short.as_bytes(), "r", strict.clone()).unwrap();
+        assert!(rows.next().unwrap().is_ok());
+        assert!(rows.next().unwrap().is_ok());
+        let err = rows.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Line 1: expected 5 rows, found 2");
+        assert!(rows.next().is_none());
+        let lenient = RowReader::new(short.as_bytes(), "r", ParseOptions::default()).unwrap();
+        assert_eq!(lenient.count(), 2);
+        let exact = RowReader::new("r[1]{a}:\n  1\nb: 2".as_bytes(), "r", strict).unwrap();
+        assert!(exact.collect::<Result<Vec<_>, _>>().is_ok());
+    }
 
-        // Mixed types
-        let result = parse_value("42, hello, true");
-        if let ToonValue::Array(items) = result {
-            assert_eq!(items.len(), 3);
-            assert_eq!(items[0], ToonValue::Int(42));
-            assert_eq!(items[1], ToonValue::String("hello".to_string()));
-            assert_eq!(items[2], ToonValue::Bool(true));
-        } else {
-            panic!("Expected array");
-        }
+    #[test]
+    fn test_encode_tabular_array() {
+        let users = ToonValue::Array(vec![
+            row(&[
+                ("id", ToonValue::Int(1)),
+                ("name", ToonValue::String("Ada".to_string())),
+            ]),
+            row(&[
+                ("id", ToonValue::Int(2)),
+                ("name", ToonValue::String("Bob, Jr".to_string())),
+            ]),
+        ]);
+        let val = ToonValue::Map(ToonMap::from([("users".to_string(), users.clone())]));
+
+        let encoded = encode(&val);
+        assert_eq!(encoded, "users[2]{id,name}:\n  1,Ada\n  2,\"Bob, Jr\"");
+        assert_eq!(parse(&encoded).unwrap(), val);
+
+        let root = encode(&users);
+        assert_eq!(root, "[2]{id,name}:\n  1,Ada\n  2,\"Bob, Jr\"");
+        assert_eq!(parse(&root).unwrap(), users);
+
+        let opts = EncodeOptions {
+            delimiter: Delimiter::Pipe,
+            lengthless: true,
+            ..EncodeOptions::default()
+        };
+        let encoded = encode_with(&val, &opts);
+        assert_eq!(encoded, "users[|]{id|name}:\n  1|Ada\n  2|\"Bob, Jr\"");
+        assert_eq!(parse(&encoded).unwrap(), val);
     }
 
     #[test]
-    fn test_parse_deep_nesting() {
-        let input = "l1:\n  l2:\n    l3:\n      l4:\n        l5: value";
-        let val = parse(input).unwrap();
+    fn test_encode_non_tabular_maps_in_array() {
+        let val = ToonValue::Map(ToonMap::from([(
+            "items".to_string(),
+            ToonValue::Array(vec![
+                row(&[("a", ToonValue::Int(1))]),
+                row(&[("b", ToonValue::Array(vec![ToonValue::Int(2)]))]),
+            ]),
+        )]));
+        assert_eq!(encode(&val), "items:\n  0:\n    a: 1\n  1:\n    b: 2");
+    }
 
-        // Navigate through nested structure
-        if let ToonValue::Map(m) = val {
-            assert_eq!(m.len(), 1);
-            if let ToonValue::Map(l2) = &m[0].1 {
-                if let ToonValue::Map(l3) = &l2[0].1 {
-                    if let ToonValue::Map(l4) = &l3[0].1 {
-                        if let ToonValue::Map(l5) = &l4[0].1 {
-                            assert_eq!(l5[0].0, "l5");
-                            assert_eq!(l5[0].1, ToonValue::String("value".to_string()));
-                        } else {
-                            panic!("Expected l4 to have map");
-                        }
-                    } else {
-                        panic!("Expected l3 to have map");
-                    }
-                } else {
-                    panic!("Expected l2 to have map");
-                }
-            } else {
-                panic!("Expected l1 to have map");
+    #[test]
+    fn test_stream_encoder_write_with() {
+        let mut out = Vec::new();
+        let mut encoder = StreamEncoder::new(&mut out, EncodeOptions::default());
+        encoder
+            .write_with(|out| out.write_str("a: 1\nb:\n  c: 2\n"))
+            .unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a: 1\nb:\n  c: 2");
+    }
+
+    #[test]
+    fn test_stream_encoder_table() {
+        let opts = EncodeOptions {
+            lengthless: true,
+            ..EncodeOptions::default()
+        };
+        let mut out = Vec::new();
+        let mut encoder = StreamEncoder::new(&mut out, opts);
+        encoder.entry("count", &ToonValue::Int(2)).unwrap();
+        encoder.table_header("rows", 0, &["id", "ok"]).unwrap();
+        encoder
+            .table_row(&[ToonValue::Int(1), ToonValue::Bool(true)])
+            .unwrap();
+        encoder
+            .table_row(&[ToonValue::Int(2), ToonValue::Null])
+            .unwrap();
+        encoder.finish().unwrap();
+
+        let encoded = String::from_utf8(out).unwrap();
+        assert_eq!(encoded, "count: 2\nrows[]{id,ok}:\n  1,true\n  2,null");
+        assert!(parse_with(
+            &encoded,
+            &ParseOptions {
+                strict: true,
+                ..ParseOptions::default()
             }
-        } else {
-            panic!("Expected root map");
+        )
+        .is_ok());
+    }
+
+    // ============================================================================
+    // SECTION 7: PATH DECODING
+    // ============================================================================
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(
+            split_path("users[0].name").unwrap(),
+            split_path("users.0.name").unwrap()
+        );
+        assert_eq!(
+            split_path("\"v1.2\".notes[3]").unwrap(),
+            vec![
+                PathSegment::Key("v1.2".to_string()),
+                PathSegment::Key("notes".to_string()),
+                PathSegment::Index(3),
+            ]
+        );
+        assert!(split_path("").unwrap().is_empty());
+        assert!(split_path("users[x]").is_err());
+        assert!(split_path("\"open").is_err());
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let input = "settings:\n  cache:\n    ttl: 60\n  llm:\n    model: gpt\n    tags: a, b\nusers[2]{id,name}:\n  1,Ada\n  2,Bob\nitems:\n  0:\n    id: 7\nempty:\n";
+        let opts = ParseOptions::default();
+        let at = |path: &str| parse_path(input, path, &opts).unwrap();
+
+        assert_eq!(
+            at("settings.llm.model"),
+            Some(ToonValue::String("gpt".to_string()))
+        );
+        assert_eq!(
+            at("settings.llm.tags[1]"),
+            Some(ToonValue::String("b".to_string()))
+        );
+        assert_eq!(
+            at("users[1].name"),
+            Some(ToonValue::String("Bob".to_string()))
+        );
+        assert_eq!(at("items.0.id"), Some(ToonValue::Int(7)));
+        assert_eq!(at("empty"), Some(ToonValue::Map(ToonMap::new())));
+        assert_eq!(at("settings.llm.missing"), None);
+        assert_eq!(at("users[5]"), None);
+        assert_eq!(at(""), Some(parse(input).unwrap()));
+
+        // Every path agrees with a full parse
+        let full = parse(input).unwrap();
+        for path in [
+            "settings",
+            "settings.cache",
+            "users",
+            "users.0",
+            "items",
+            "items[0]",
+        ] {
+            let segments = split_path(path).unwrap();
+            assert_eq!(at(path), select(full.clone(), &segments), "{path}");
+        }
+    }
+
+    #[test]
+    fn test_select_keys() {
+        let value = parse("a: 1\n0: zero\n+1: one\nb:\n  c: true").unwrap();
+        let at = |path: &str| select(value.clone(), &split_path(path).unwrap());
+
+        assert_eq!(at("b.c"), Some(ToonValue::Bool(true)));
+        assert_eq!(at("0"), Some(ToonValue::String("zero".to_string())));
+        assert_eq!(at("[1]"), Some(ToonValue::String("one".to_string())));
+        assert_eq!(at("\"+1\""), Some(ToonValue::String("one".to_string())));
+        assert_eq!(at("b.d"), None);
+        assert_eq!(at("c"), None);
+    }
+
+    #[test]
+    fn test_parse_path_skips_siblings() {
+        // The malformed row is never parsed since only `b` is requested
+        let input = "a[1]{x,y}:\n  1\nb:\n  c: true\nb:\n  c: false\n";
+        assert_eq!(
+            parse_path(input, "b.c", &ParseOptions::default()).unwrap(),
+            Some(ToonValue::Bool(false))
+        );
+        assert_eq!(
+            parse_path("[2]{id}:\n  1\n  2", "1.id", &ParseOptions::default()).unwrap(),
+            Some(ToonValue::Int(2))
+        );
+    }
+
+    // ============================================================================
+    // SECTION 8: SINKS
+    // ============================================================================
+
+    /// Records the events it receives, one string each.
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Sink<'_> for Recorder {
+        fn begin_map(&mut self, len_hint: usize) -> Result<(), ToonError> {
+            self.0.push(format!("map({len_hint})"));
+            Ok(())
+        }
+
+        fn begin_list(&mut self, len_hint: usize) -> Result<(), ToonError> {
+            self.0.push(format!("list({len_hint})"));
+            Ok(())
+        }
+
+        fn key(&mut self, key: Cow<'_, str>) -> Result<(), ToonError> {
+            self.0.push(format!("key {key}"));
+            Ok(())
+        }
+
+        fn scalar(&mut self, value: Scalar<'_>) -> Result<(), ToonError> {
+            self.0.push(format!("{value:?}"));
+            Ok(())
+        }
+
+        fn end(&mut self) -> Result<(), ToonError> {
+            self.0.push("end".to_string());
+            Ok(())
         }
     }
 
     #[test]
-    fn test_parse_multiple_keys() {
-        let input = "name: Alice\nage: 30\nemail: alice@example.com";
-        let val = parse(input).unwrap();
+    fn test_parse_into_sink() {
+        let mut recorder = Recorder::default();
+        let input = "name: \"Ada\"\ntags: a, [b, c]\nusers[1]{id}:\n  7\nempty:";
+        parse_into(input, &ParseOptions::default(), &mut recorder).unwrap();
+        assert_eq!(
+            recorder.0,
+            [
+                "map(0)",
+                "key name",
+                "Str(\"Ada\")",
+                "key tags",
+                "list(2)",
+                "Str(\"a\")",
+                "list(2)",
+                "Str(\"b\")",
+                "Str(\"c\")",
+                "end",
+                "end",
+                "key users",
+                "list(1)",
+                "map(1)",
+                "key id",
+                "Int(7)",
+                "end",
+                "end",
+                "key empty",
+                "map(0)",
+                "end",
+                "end",
+            ]
+        );
+
+        // A root array is the document itself
+        let mut recorder = Recorder::default();
+        parse_into("[2]: 1, 2", &ParseOptions::default(), &mut recorder).unwrap();
+        assert_eq!(recorder.0, ["list(2)", "Int(1)", "Int(2)", "end"]);
+    }
+
+    #[test]
+    fn test_root_array_detection() {
+        let opts = ParseOptions::default();
+        // Lines without a key are skipped around a root array
+        assert_eq!(
+            parse_with("note\n[2]: 1, 2\n", &opts).unwrap(),
+            ToonValue::Array(vec![ToonValue::Int(1), ToonValue::Int(2)])
+        );
+        // Any other entry makes the document a map
+        assert_eq!(
+            parse_with("[1]: 1\nb: 2", &opts).unwrap(),
+            ToonValue::Map(ToonMap::from([
+                (String::new(), ToonValue::Array(vec![ToonValue::Int(1)])),
+                ("b".to_string(), ToonValue::Int(2)),
+            ]))
+        );
+        let strict = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+        assert!(parse_with("note\n[1]: 1", &strict).is_err());
+    }
+
+    // ============================================================================
+    // SECTION 9: INLINE TOKENIZER
+    // ============================================================================
+
+    #[test]
+    fn test_escaped_backslash_before_n() {
+        // `\\n` is a backslash followed by `n`, not a newline
+        let val = ToonValue::Map(ToonMap::from([(
+            "path".to_string(),
+            ToonValue::String("C:\\new, old".to_string()),
+        )]));
+        assert_eq!(parse(&encode(&val)).unwrap(), val);
+    }
+
+    #[test]
+    fn test_unbalanced_brackets() {
+        assert_eq!(
+            parse_value("[\"]\", a]"),
+            ToonValue::Array(vec![
+                ToonValue::String("]".to_string()),
+                ToonValue::String("a".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse_value("[a,b]x"),
+            ToonValue::String("[a,b]x".to_string())
+        );
+        assert_eq!(parse_value("[a, b"), ToonValue::String("[a, b".to_string()));
+    }
 
-        if let ToonValue::Map(m) = val {
-            assert_eq!(m.len(), 3);
-            assert_eq!(m[0].0, "name");
-            assert_eq!(m[0].1, ToonValue::String("Alice".to_string()));
-            assert_eq!(m[1].0, "age");
-            assert_eq!(m[1].1, ToonValue::Int(30));
-            assert_eq!(m[2].0, "email");
-            assert_eq!(m[2].1, ToonValue::String("alice@example.com".to_string()));
-        } else {
+    #[test]
+    fn test_multibyte_enclosed_list() {
+        assert_eq!(
+            parse_value("[é, ü]"),
+            ToonValue::Array(vec![
+                ToonValue::String("é".to_string()),
+                ToonValue::String("ü".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_inline_depth_limit() {
+        let nested = format!("v: {}x{}", "[".repeat(500), "]".repeat(500));
+        let err = parse(&nested).unwrap_err();
+        assert!(err.to_string().contains("depth limit"));
+
+        let opts = ParseOptions {
+            max_depth: 1000,
+            ..ParseOptions::default()
+        };
+        let ToonValue::Map(entries) = parse_with(&nested, &opts).unwrap() else {
             panic!("Expected map");
+        };
+        let mut value = &entries[0];
+        let mut depth = 0;
+        while let ToonValue::Array(items) = value {
+            value = &items[0];
+            depth += 1;
         }
+        assert_eq!(depth, 500);
     }
 
     // ============================================================================
-    // SECTION 2: ENCODING TESTS
+    // SECTION 10: BORROWED VALUES
     // ============================================================================
 
     #[test]
-    fn test_encode_simple() {
-        let mut user_map = Vec::new();
-        user_map.push(("id".to_string(), ToonValue::Int(123)));
-        user_map.push((
-            "email".to_string(),
-            ToonValue::String("ada@example.com".to_string()),
+    fn test_parse_borrowed() {
+        let input = "name: Ada\nquote: \"say \\\"hi\\\"\"\nrows[1]{id,\"full name\"}:\n  1, Bob";
+        let doc = parse_borrowed(input, &ParseOptions::default()).unwrap();
+        let BorrowedValue::Map(entries) = &doc else {
+            panic!("Expected map, got {:?}", doc);
+        };
+
+        // Plain keys and strings point into the input
+        let (key, value) = entries.get_index(0).unwrap();
+        assert!(matches!(key, Cow::Borrowed("name")));
+        assert!(matches!(value, BorrowedValue::String(Cow::Borrowed("Ada"))));
+        let range = input.as_bytes().as_ptr_range();
+        assert!(range.contains(&key.as_ptr()));
+
+        // Escapes have to be resolved into a copy
+        assert_eq!(
+            entries[1],
+            BorrowedValue::String(Cow::Owned("say \"hi\"".to_string()))
+        );
+        assert!(matches!(&entries[1], BorrowedValue::String(Cow::Owned(_))));
+
+        let BorrowedValue::Array(rows) = &entries[2] else {
+            panic!("Expected rows, got {:?}", entries[2]);
+        };
+        let BorrowedValue::Map(cells) = &rows[0] else {
+            panic!("Expected row, got {:?}", rows[0]);
+        };
+        assert!(matches!(
+            cells.get_index(1).unwrap().0,
+            Cow::Borrowed("full name")
         ));
 
-        let mut root_map = Vec::new();
-        root_map.push(("user".to_string(), ToonValue::Map(user_map)));
+        assert_eq!(doc.into_owned(), parse(input).unwrap());
+    }
 
-        let val = ToonValue::Map(root_map);
-        let encoded = encode(&val);
+    // ============================================================================
+    // SECTION 11: SERDE
+    // ============================================================================
 
-        let expected = "user:\n  id: 123\n  email: ada@example.com";
-        assert_eq!(encoded, expected);
+    #[cfg(feature = "serde")]
+    mod serde_support {
+        use super::super::{from_reader, from_str, parse, to_string, to_writer, ToonValue};
+        use serde::{Deserialize, Serialize};
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Role {
+            Admin,
+            Guest,
+            Custom(String),
+            Limited { quota: u32 },
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct User {
+            id: u64,
+            name: String,
+            nickname: Option<String>,
+            role: Role,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Row {
+            id: u64,
+            name: String,
+            active: bool,
+            score: Option<f64>,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Team {
+            name: String,
+            tags: Vec<String>,
+            members: Vec<Row>,
+            labels: BTreeMap<String, i32>,
+        }
+
+        #[test]
+        fn test_struct_roundtrip() {
+            let users = vec![
+                User {
+                    id: 1,
+                    name: "Ada".to_string(),
+                    nickname: None,
+                    role: Role::Admin,
+                },
+                User {
+                    id: 2,
+                    name: "Bob, Jr.".to_string(),
+                    nickname: Some("bobby".to_string()),
+                    role: Role::Custom("ops".to_string()),
+                },
+                User {
+                    id: 3,
+                    name: "Cy".to_string(),
+                    nickname: Some("42".to_string()),
+                    role: Role::Limited { quota: 5 },
+                },
+                User {
+                    id: 4,
+                    name: "Di".to_string(),
+                    nickname: None,
+                    role: Role::Guest,
+                },
+            ];
+            let text = to_string(&users).unwrap();
+            assert_eq!(from_str::<Vec<User>>(&text).unwrap(), users);
+        }
+
+        #[test]
+        fn test_vec_of_structs_is_a_table() {
+            let team = Team {
+                name: "core".to_string(),
+                tags: vec!["rust".to_string()],
+                members: vec![
+                    Row {
+                        id: 1,
+                        name: "Ada".to_string(),
+                        active: true,
+                        score: Some(9.5),
+                    },
+                    Row {
+                        id: 2,
+                        name: "Bob".to_string(),
+                        active: false,
+                        score: None,
+                    },
+                ],
+                labels: BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 2)]),
+            };
+            let text = to_string(&team).unwrap();
+            assert!(text.contains("members[2]{id,name,active,score}:"));
+            assert!(text.contains("2,Bob,false,null"));
+            assert_eq!(from_str::<Team>(&text).unwrap(), team);
+
+            let mut out = Vec::new();
+            to_writer(&mut out, &team).unwrap();
+            assert_eq!(out, text.as_bytes());
+            assert_eq!(from_reader::<_, Team>(out.as_slice()).unwrap(), team);
+        }
+
+        #[test]
+        fn test_empty_and_single_item_lists() {
+            let team = Team {
+                name: "empty".to_string(),
+                tags: Vec::new(),
+                members: Vec::new(),
+                labels: BTreeMap::new(),
+            };
+            assert_eq!(from_str::<Team>(&to_string(&team).unwrap()).unwrap(), team);
+
+            let nested = vec![vec![1, 2], vec![3]];
+            let text = to_string(&nested).unwrap();
+            assert_eq!(parse(&text).unwrap(), parse("[1, 2], [3]").unwrap());
+            assert_eq!(from_str::<Vec<Vec<i32>>>(&text).unwrap(), nested);
+        }
+
+        #[test]
+        fn test_serde_errors() {
+            assert!(to_string(&u64::MAX).is_err());
+            let err = from_str::<Row>("id: one\nname: Ada").unwrap_err();
+            assert!(err.to_string().contains("invalid type"));
+            assert_eq!(
+                from_str::<Partial>("value: 1").unwrap_err().to_string(),
+                "missing field `other`"
+            );
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Partial {
+            value: i32,
+            other: i32,
+        }
+
+        #[test]
+        fn test_scalar_like_strings_roundtrip() {
+            for s in ["3.0", "007", "null", "true", "false", "-5", "1e3"] {
+                let text = to_string(s).unwrap();
+                assert_eq!(text, format!("\"{s}\""));
+                assert_eq!(from_str::<String>(&text).unwrap(), s);
+            }
+            let some = Some("null".to_string());
+            assert_eq!(
+                from_str::<Option<String>>(&to_string(&some).unwrap()).unwrap(),
+                some
+            );
+            let tags = vec!["3.0".to_string(), "007".to_string(), "null".to_string()];
+            let text = to_string(&tags).unwrap();
+            assert_eq!(from_str::<Vec<String>>(&text).unwrap(), tags);
+        }
+
+        #[test]
+        fn test_unit_values_are_null() {
+            assert_eq!(to_string(&Option::<i32>::None).unwrap(), "null");
+            assert_eq!(parse(&to_string(&()).unwrap()).unwrap(), ToonValue::Null);
+        }
     }
 
+    // ============================================================================
+    // SECTION 12: VALUE API
+    // ============================================================================
+
     #[test]
-    fn test_encode_primitives() {
-        assert_eq!(encode(&ToonValue::Null), "null");
-        assert_eq!(encode(&ToonValue::Bool(true)), "true");
-        assert_eq!(encode(&ToonValue::Bool(false)), "false");
-        assert_eq!(encode(&ToonValue::Int(42)), "42");
-        assert_eq!(encode(&ToonValue::Int(-99)), "-99");
-        assert_eq!(encode(&ToonValue::Float(3.14)), "3.14");
-        assert_eq!(encode(&ToonValue::String("hello".to_string())), "hello");
+    fn test_value_accessors() {
+        let value =
+            parse("user:\n  id: 7\n  name: Ada\n  score: 1.5\n  tags[2]: a,b\n  admin: true")
+                .unwrap();
+
+        let user = &value["user"];
+        assert_eq!(user["id"].as_i64(), Some(7));
+        assert_eq!(user["id"].as_f64(), Some(7.0));
+        assert_eq!(user["name"].as_str(), Some("Ada"));
+        assert_eq!(user["score"].as_f64(), Some(1.5));
+        assert_eq!(user["admin"].as_bool(), Some(true));
+        assert_eq!(user["tags"][1].as_str(), Some("b"));
+        assert_eq!(user["tags"].as_array().map(Vec::len), Some(2));
+        assert_eq!(user.as_map().map(ToonMap::len), Some(5));
+        assert_eq!(user.get("name"), Some(&ToonValue::from("Ada")));
+
+        // Misses read as null instead of panicking
+        assert!(value["missing"]["deeper"].is_null());
+        assert!(user["tags"][5].is_null());
+        assert!(user["name"][0].is_null());
+        assert_eq!(user.get("missing"), None);
+        assert_eq!(user["name"].as_i64(), None);
     }
 
     #[test]
-    fn test_encode_quoted_strings() {
-        // Strings with newlines should be quoted
-        let val = ToonValue::String("Line 1\nLine 2".to_string());
-        let encoded = encode(&val);
-        assert!(encoded.contains('"') && encoded.contains("\\n"));
-
-        // Strings with colons should be quoted
-        let val = ToonValue::String("key: value".to_string());
-        let encoded = encode(&val);
-        assert!(encoded.contains('"'));
+    fn test_value_get_mut() {
+        let mut value = ToonValue::Map(ToonMap::from([
+            ("a".to_string(), ToonValue::Int(1)),
+            ("b".to_string(), ToonValue::Int(2)),
+        ]));
+        *value.get_mut("b").unwrap() = ToonValue::Int(3);
+        assert_eq!(value.as_map().unwrap()[1], ToonValue::Int(3));
+        assert_eq!(value.get_mut("c"), None);
+    }
 
-        // Strings with quotes should be escaped
-        let val = ToonValue::String("He said \"hello\"".to_string());
-        let encoded = encode(&val);
-        assert!(encoded.contains("\\\""));
+    #[test]
+    fn test_value_from_impls() {
+        assert_eq!(ToonValue::from(true), ToonValue::Bool(true));
+        assert_eq!(ToonValue::from(7u8), ToonValue::Int(7));
+        assert_eq!(ToonValue::from(-7i32), ToonValue::Int(-7));
+        assert_eq!(ToonValue::from(0.5f32), ToonValue::Float(0.5));
+        assert_eq!(ToonValue::from("x"), ToonValue::String("x".to_string()));
+        assert_eq!(ToonValue::from(None::<i64>), ToonValue::Null);
+        assert_eq!(
+            ToonValue::from(vec![1, 2]),
+            ToonValue::Array(vec![ToonValue::Int(1), ToonValue::Int(2)])
+        );
+        assert_eq!(
+            [("a", 1), ("b", 2)].into_iter().collect::<ToonValue>(),
+            ToonValue::Map(ToonMap::from([
+                ("a".to_string(), ToonValue::Int(1)),
+                ("b".to_string(), ToonValue::Int(2)),
+            ]))
+        );
+        assert_eq!(
+            (1..=2).map(ToonValue::from).collect::<ToonValue>(),
+            ToonValue::from(vec![1, 2])
+        );
     }
 
     #[test]
-    fn test_encode_arrays() {
-        let items = vec![ToonValue::Int(1), ToonValue::Int(2), ToonValue::Int(3)];
-        let val = ToonValue::Array(items);
-        let encoded = encode(&val);
-        assert!(encoded.contains("1, 2, 3"));
+    fn test_value_display_and_from_str() {
+        let value: ToonValue = "name: Ada\nids[2]: 1,2".parse().unwrap();
+        assert_eq!(value.to_string(), encode(&value));
+        assert_eq!(value.to_string().parse::<ToonValue>().unwrap(), value);
+        assert!("rows[1]{a,b}:\n  1".parse::<ToonValue>().is_err());
     }
 
     #[test]
-    fn test_encode_mixed_array_types() {
-        let items = vec![
-            ToonValue::Int(42),
-            ToonValue::String("hello".to_string()),
-            ToonValue::Bool(true),
-            ToonValue::Null,
-        ];
-        let val = ToonValue::Array(items);
-        let encoded = encode(&val);
-        assert!(encoded.contains("42"));
-        assert!(encoded.contains("hello"));
-        assert!(encoded.contains("true"));
-        assert!(encoded.contains("null"));
+    fn test_toon_macro() {
+        let name = "Ada";
+        let value = crate::toon!({
+            "name": name,
+            "age": 36 + 1,
+            "score": -1.5,
+            "manager": null,
+            "tags": ["math", null, [1, 2], {"nested": true}],
+            (format!("k{}", 1)): {},
+            "empty": [],
+        });
+
+        assert_eq!(
+            value,
+            ToonValue::Map(ToonMap::from([
+                ("name".to_string(), ToonValue::from("Ada")),
+                ("age".to_string(), ToonValue::Int(37)),
+                ("score".to_string(), ToonValue::Float(-1.5)),
+                ("manager".to_string(), ToonValue::Null),
+                (
+                    "tags".to_string(),
+                    ToonValue::Array(vec![
+                        ToonValue::from("math"),
+                        ToonValue::Null,
+                        ToonValue::from(vec![1, 2]),
+                        ToonValue::Map(ToonMap::from([(
+                            "nested".to_string(),
+                            ToonValue::Bool(true)
+                        )])),
+                    ])
+                ),
+                ("k1".to_string(), ToonValue::Map(ToonMap::new())),
+                ("empty".to_string(), ToonValue::Array(vec![])),
+            ]))
+        );
+        assert_eq!(crate::toon!(null), ToonValue::Null);
+        assert_eq!(crate::toon!("x"), ToonValue::from("x"));
+        assert_eq!(crate::toon!([1, 2,]), ToonValue::from(vec![1, 2]));
     }
 
     // ============================================================================
-    // SECTION 3: ROUNDTRIP TESTS (ENCODE -> DECODE CONSISTENCY)
+    // SECTION 13: DUPLICATE KEYS
     // ============================================================================
 
+    fn parse_keeping(input: &str, duplicate_keys: DuplicateKeys) -> Result<ToonValue, ToonError> {
+        let opts = ParseOptions {
+            duplicate_keys,
+            ..ParseOptions::default()
+        };
+        parse_with(input, &opts)
+    }
+
     #[test]
-    fn test_roundtrip_simple_map() {
-        let input = "user:\n  id: 123\n  email: ada@example.com";
-        let parsed = parse(input).unwrap();
-        let encoded = encode(&parsed);
-        let reparsed = parse(&encoded).unwrap();
-        assert_eq!(parsed, reparsed);
+    fn test_duplicate_keys_last_wins_by_default() {
+        let val = parse("a: 1\nb: 2\na: 3").unwrap();
+        let map = val.as_map().unwrap();
+        // Like a PHP array: the first position, the last value
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(val["a"], ToonValue::Int(3));
     }
 
     #[test]
-    fn test_roundtrip_nested_structure() {
-        let input = "company:\n  name: TechCorp\n  departments:\n    engineering:\n      employees: 10\n    sales:\n      employees: 5";
-        let parsed = parse(input).unwrap();
-        let encoded = encode(&parsed);
-        let reparsed = parse(&encoded).unwrap();
-        assert_eq!(parsed, reparsed);
+    fn test_duplicate_keys_first_wins() {
+        let val = parse_keeping("a: 1\nb: 2\na:\n  c: 3", DuplicateKeys::First).unwrap();
+        assert_eq!(val["a"], ToonValue::Int(1));
+        assert_eq!(val.as_map().unwrap().len(), 2);
     }
 
     #[test]
-    fn test_roundtrip_arrays() {
-        let items = vec![ToonValue::Int(1), ToonValue::Int(2), ToonValue::Int(3)];
-        let val = ToonValue::Array(items.clone());
-        let encoded = encode(&val);
-        let decoded = parse(&encoded).unwrap();
-        assert_eq!(ToonValue::Array(items), decoded);
+    fn test_duplicate_keys_error() {
+        let err = parse_keeping("user:\n  id: 1\n  id: 2", DuplicateKeys::Error).unwrap_err();
+        assert_eq!(err.to_string(), "Line 3: duplicate key 'id'");
+
+        // Repeated fields of a table header repeat the key in every row
+        let table = "rows[1]{id,id}:\n  1,2";
+        assert!(parse_keeping(table, DuplicateKeys::Error).is_err());
+        assert_eq!(
+            parse_keeping(table, DuplicateKeys::First).unwrap()["rows"][0]["id"],
+            ToonValue::Int(1)
+        );
+        let opts = ParseOptions {
+            duplicate_keys: DuplicateKeys::Error,
+            ..ParseOptions::default()
+        };
+        assert!(parse_borrowed(table, &opts).is_err());
+        assert!(parse_path(table, "rows[0]", &opts).is_err());
     }
 
     #[test]
-    fn test_full_example() {
-        let input = "user:\n  id: 123\n  email: ada@example.com\n  metadata:\n    active: true\n    score: 9.5";
-        let val = parse(input).unwrap();
-        let encoded = encode(&val);
-        assert_eq!(encoded, input);
+    fn test_duplicate_keys_in_paths() {
+        let input = "a: 1\nb:\n  c: 2\na: 3\nb:\n  c: 4";
+        let with = |duplicate_keys| ParseOptions {
+            duplicate_keys,
+            ..ParseOptions::default()
+        };
+
+        let first = with(DuplicateKeys::First);
+        assert_eq!(parse_with(input, &first).unwrap()["a"], ToonValue::Int(1));
+        assert_eq!(
+            parse_path(input, "a", &first).unwrap(),
+            Some(ToonValue::Int(1))
+        );
+        assert_eq!(
+            parse_path(input, "b.c", &first).unwrap(),
+            Some(ToonValue::Int(2))
+        );
+
+        let last = with(DuplicateKeys::Last);
+        assert_eq!(
+            parse_path(input, "a", &last).unwrap(),
+            Some(ToonValue::Int(3))
+        );
+        assert_eq!(
+            parse_path(input, "b.c", &last).unwrap(),
+            Some(ToonValue::Int(4))
+        );
+
+        let error = with(DuplicateKeys::Error);
+        let err = parse_path(input, "a", &error).unwrap_err();
+        assert!(matches!(err, ToonError::DuplicateKey { ref key, .. } if key == "a"));
+        assert_eq!(err.to_string(), "Line 4: duplicate key 'a'");
+        assert!(parse_path(input, "b.c", &error).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_keys_from_name() {
+        assert_eq!(
+            DuplicateKeys::from_name("first"),
+            Some(DuplicateKeys::First)
+        );
+        assert_eq!(DuplicateKeys::from_name("last"), Some(DuplicateKeys::Last));
+        assert_eq!(
+            DuplicateKeys::from_name("error"),
+            Some(DuplicateKeys::Error)
+        );
+        assert_eq!(DuplicateKeys::from_name("merge"), None);
     }
 
     // ============================================================================
-    // SECTION 4: EDGE CASES AND SPECIAL CHARACTERS
+    // SECTION 14: ERRORS
     // ============================================================================
 
+    fn parse_strict(input: &str) -> ToonError {
+        let opts = ParseOptions {
+            strict: true,
+            ..ParseOptions::default()
+        };
+        parse_with(input, &opts).unwrap_err()
+    }
+
     #[test]
-    fn test_empty_string_key() {
-        let mut map = Vec::new();
-        map.push(("empty_value".to_string(), ToonValue::String("".to_string())));
-        let val = ToonValue::Map(map);
-        let encoded = encode(&val);
-        assert!(encoded.contains("empty_value:") && encoded.contains("\"\""));
+    fn test_error_variants_and_spans() {
+        let err = parse_strict("a: 1\n  b: 2");
+        assert!(matches!(err, ToonError::UnexpectedIndent { .. }));
+        assert_eq!(err.span(), Span::new(2, 3));
+
+        let err = parse_strict("a: 1\njunk");
+        assert!(matches!(err, ToonError::MissingKey { .. }));
+        assert_eq!(err.span(), Span::new(2, 1));
+
+        let err = parse_strict("a: \"x\\q\"");
+        assert!(matches!(err, ToonError::InvalidEscape { escape: 'q', .. }));
+        assert_eq!(err.span(), Span::new(1, 6));
+        assert_eq!(err.to_string(), "Line 1, column 6: invalid escape '\\q'");
+
+        let err = parse_strict("a: 1\nb: x, \"open");
+        assert!(matches!(err, ToonError::UnterminatedString { .. }));
+        assert_eq!(err.span(), Span::new(2, 7));
     }
 
     #[test]
-    fn test_special_characters_in_string() {
-        let test_strings = vec!["!@#$%^&*()", "path/to/file"];
+    fn test_error_count_mismatch() {
+        let err = parse_strict("tags[3]: a,b");
+        assert!(matches!(
+            err,
+            ToonError::CountMismatch {
+                kind: CountKind::Items,
+                expected: 3,
+                found: 2,
+                ..
+            }
+        ));
+        assert_eq!(err.to_string(), "Line 1: expected 3 items, found 2");
+
+        // Rows with missing cells fail in lenient mode too
+        let err = parse("x: 1\nrows[2]{a,b}:\n  1,2\n  3").unwrap_err();
+        assert!(matches!(
+            err,
+            ToonError::CountMismatch {
+                kind: CountKind::Fields,
+                expected: 2,
+                found: 1,
+                ..
+            }
+        ));
+        assert_eq!(err.span(), Span::new(4, 3));
+    }
 
-        for test_str in test_strings {
-            let val = ToonValue::String(test_str.to_string());
-            let encoded = encode(&val);
-            let decoded = parse(&encoded).unwrap();
+    #[test]
+    fn test_error_depth_and_paths() {
+        let opts = ParseOptions {
+            max_depth: 1,
+            ..ParseOptions::default()
+        };
+        let err = parse_with("a:\n  b:\n    c: 1", &opts).unwrap_err();
+        assert!(matches!(err, ToonError::DepthExceeded { max_depth: 1, .. }));
+        assert_eq!(err.span().line, 3);
+
+        let err = split_path("users[x]").unwrap_err();
+        assert!(matches!(err, ToonError::InvalidPath { .. }));
+        assert_eq!(err.to_string(), "Invalid path 'users[x]'");
+
+        let mut rows =
+            RowReader::new("a: 1\nb: 2".as_bytes(), "users", ParseOptions::default()).unwrap();
+        let err = rows.next().unwrap().unwrap_err();
+        assert!(matches!(err, ToonError::TableNotFound { .. }));
+    }
 
-            if let ToonValue::String(decoded_str) = decoded {
-                assert_eq!(test_str, decoded_str, "Failed roundtrip for: {}", test_str);
-            } else {
-                panic!("Expected string, got {:?}", decoded);
+    #[test]
+    fn test_error_depth_counts_maps_and_lists() {
+        let depth = |max_depth| ParseOptions {
+            max_depth,
+            ..ParseOptions::default()
+        };
+        // Scalars do not nest, so they may sit in a map at the deepest level
+        assert!(parse_with("a:\n  b: 1", &depth(1)).is_ok());
+        assert!(parse_with("a:\n  b: 1", &depth(0)).is_err());
+
+        // The rows of a table are maps one level below it
+        let table = "a:\n  users[1]{id}:\n    1";
+        assert!(parse_with(table, &depth(3)).is_ok());
+        let err = parse_with(table, &depth(2)).unwrap_err();
+        assert!(matches!(err, ToonError::DepthExceeded { max_depth: 2, .. }));
+        assert_eq!(err.span(), Span::new(2, 3));
+        assert!(parse_with("tags: a, b", &depth(0)).is_err());
+        assert!(parse_with("a:\n  empty:", &depth(1)).is_err());
+    }
+
+    #[test]
+    fn test_error_depth_limit_is_capped() {
+        let opts = ParseOptions {
+            max_depth: usize::MAX,
+            ..ParseOptions::default()
+        };
+        assert_eq!(opts.depth_limit(), MAX_DEPTH_LIMIT);
+        let deep = format!("value: {}x{}", "[".repeat(5000), "]".repeat(5000));
+        let err = parse_with(&deep, &opts).unwrap_err();
+        assert!(matches!(
+            err,
+            ToonError::DepthExceeded {
+                max_depth: MAX_DEPTH_LIMIT,
+                ..
             }
-        }
+        ));
+        let nested = format!("value: {}x{}", "[".repeat(999), "]".repeat(999));
+        assert!(parse_with(&nested, &opts).is_ok());
     }
 
     #[test]
-    fn test_unicode_characters() {
-        let unicode_strings = vec!["Hello 🌍", "你好世界", "مرحبا بالعالم", "Привет мир"];
+    fn test_error_lenient_strings() {
+        // Without strict mode odd strings are read as they are
+        assert_eq!(parse("a: \"x\\q\"").unwrap()["a"], ToonValue::from("x\\q"));
+        assert_eq!(parse("a: \"open").unwrap()["a"], ToonValue::from("\"open"));
+    }
 
-        for test_str in unicode_strings {
-            let val = ToonValue::String(test_str.to_string());
-            let encoded = encode(&val);
-            let decoded = parse(&encoded).unwrap();
+    /// A sink refusing any value, standing in for a caller's own errors.
+    struct Refusing;
 
-            if let ToonValue::String(decoded_str) = decoded {
-                assert_eq!(test_str, decoded_str, "Failed roundtrip for: {}", test_str);
-            } else {
-                panic!("Expected string, got {:?}", decoded);
-            }
+    impl<'a> Sink<'a> for Refusing {
+        fn begin_map(&mut self, _len_hint: usize) -> Result<(), ToonError> {
+            Ok(())
+        }
+        fn begin_list(&mut self, _len_hint: usize) -> Result<(), ToonError> {
+            Ok(())
+        }
+        fn key(&mut self, _key: Cow<'a, str>) -> Result<(), ToonError> {
+            Ok(())
+        }
+        fn scalar(&mut self, _value: Scalar<'a>) -> Result<(), ToonError> {
+            Err(ToonError::sink("refused"))
+        }
+        fn end(&mut self) -> Result<(), ToonError> {
+            Ok(())
         }
     }
 
     #[test]
-    fn test_large_integers() {
-        let large_int = i64::MAX;
-        let val = ToonValue::Int(large_int);
-        let encoded = encode(&val);
-        let decoded = parse(&encoded).unwrap();
-        assert_eq!(val, decoded);
+    fn test_error_from_sink() {
+        let err = parse_into("a:\n  b: 1", &ParseOptions::default(), &mut Refusing).unwrap_err();
+        assert!(matches!(err, ToonError::Sink { .. }));
+        assert_eq!(err.span().line, 2);
+        assert_eq!(
+            std::error::Error::source(&err).unwrap().to_string(),
+            "refused"
+        );
+    }
 
-        let neg_large_int = i64::MIN;
-        let val = ToonValue::Int(neg_large_int);
-        let encoded = encode(&val);
-        let decoded = parse(&encoded).unwrap();
-        assert_eq!(val, decoded);
+    // ============================================================================
+    // SECTION 15: READER
+    // ============================================================================
+
+    fn events(input: &str) -> Vec<Event<'_>> {
+        Reader::new(input, ParseOptions::default())
+            .map(|event| event.unwrap().0)
+            .collect()
     }
 
     #[test]
-    fn test_float_precision() {
-        let float_val = 3.141592653589793;
-        let val = ToonValue::Float(float_val);
-        let encoded = encode(&val);
-        let decoded = parse(&encoded).unwrap();
-        if let ToonValue::Float(f) = decoded {
-            assert!((float_val - f).abs() < 0.0001);
-        } else {
-            panic!("Expected float");
-        }
+    fn test_reader_events() {
+        let input = "user:\n  name: Ada\n  tags[2]: a,b\nrows[1]{id}:\n  7\nempty:";
+        let key = |key: &str| Event::Key(Cow::Owned(key.to_string()));
+        let str = |s: &str| Event::Scalar(Scalar::Str(Cow::Owned(s.to_string())));
+        assert_eq!(
+            events(input),
+            [
+                Event::StartMap,
+                key("user"),
+                Event::StartMap,
+                key("name"),
+                str("Ada"),
+                key("tags"),
+                Event::StartArray {
+                    len: Some(2),
+                    fields: None,
+                    delimiter: Delimiter::Comma,
+                },
+                str("a"),
+                str("b"),
+                Event::End,
+                Event::End,
+                key("rows"),
+                Event::StartArray {
+                    len: Some(1),
+                    fields: Some(vec![Cow::Borrowed("id")]),
+                    delimiter: Delimiter::Comma,
+                },
+                Event::StartMap,
+                key("id"),
+                Event::Scalar(Scalar::Int(7)),
+                Event::End,
+                Event::End,
+                key("empty"),
+                Event::StartMap,
+                Event::End,
+                Event::End,
+            ]
+        );
+        assert_eq!(events(""), [Event::Scalar(Scalar::Null)]);
+        assert_eq!(events("42"), [Event::Scalar(Scalar::Int(42))]);
     }
 
     #[test]
-    fn test_parse_empty_input() {
-        let result = parse("");
-        assert_eq!(result.unwrap(), ToonValue::Null);
+    fn test_reader_spans() {
+        let spans: Vec<_> = Reader::new("a:\n  b: x, y", ParseOptions::default())
+            .map(|event| event.unwrap().1)
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                Span::new(1, 1),  // {
+                Span::new(1, 1),  // a
+                Span::new(2, 3),  // {
+                Span::new(2, 3),  // b
+                Span::new(2, 6),  // [
+                Span::new(2, 6),  // x
+                Span::new(2, 9),  // y
+                Span::new(2, 10), // ]
+                Span::new(3, 0),  // }
+                Span::new(3, 0),  // }
+            ]
+        );
     }
 
     #[test]
-    fn test_map_with_many_entries() {
-        let mut map = Vec::new();
-        for i in 1..=50 {
-            map.push((format!("key{}", i), ToonValue::Int(i as i64)));
+    fn test_reader_from_reader_matches_str() {
+        let docs = [
+            "",
+            "a,b,c",
+            "a: 1\nb:\n  c: [1, [2, 3]]\n  d: \"x\\ny\"\ne:",
+            "data:\n  rows[2]{id,name}:\n    1,Ada\n\n    2,Bob\n  after: true\r\nend: 1",
+            "note\n[2]{id}:\n  1\n  2\n",
+        ];
+        for doc in docs {
+            let read: Vec<_> = Reader::from_reader(doc.as_bytes(), ParseOptions::default())
+                .map(|event| event.unwrap())
+                .collect();
+            let parsed: Vec<_> = Reader::new(doc, ParseOptions::default())
+                .map(|event| event.unwrap())
+                .collect();
+            assert_eq!(read, parsed, "{doc:?}");
         }
-        let val = ToonValue::Map(map);
-        let encoded = encode(&val);
-        let decoded = parse(&encoded).unwrap();
-        assert_eq!(val, decoded);
     }
 
     #[test]
-    fn test_deeply_nested_arrays() {
-        let inner = vec![ToonValue::Int(1), ToonValue::Int(2)];
-        let middle = vec![ToonValue::Array(inner)];
-        let outer = vec![ToonValue::Array(middle)];
-        let val = ToonValue::Array(outer);
+    fn test_reader_root_array_in_stream() {
+        // In memory the trailing entry makes the document a map; a stream
+        // cannot look ahead and rejects it
+        let doc = "[2]: a,b\nx: 1";
+        assert!(parse(doc).unwrap().as_map().is_some());
+        let mut events = Reader::from_reader(doc.as_bytes(), ParseOptions::default());
+        let err = events.find_map(Result::err).unwrap();
+        assert!(matches!(err, ToonError::TrailingContent { .. }));
+        assert_eq!(err.span(), Span::new(2, 1));
+        assert!(events.next().is_none());
+    }
 
-        let encoded = encode(&val);
-        // Verify nested arrays are encoded correctly
-        assert!(encoded.contains("["));
+    #[test]
+    fn test_reader_io_error() {
+        let input: &[u8] = b"a: 1\nb: \xff\n";
+        let err = Reader::from_reader(input, ParseOptions::default())
+            .find_map(Result::err)
+            .unwrap();
+        assert!(matches!(err, ToonError::Io { .. }));
+        assert_eq!(err.span().line, 2);
+    }
+
+    #[test]
+    fn test_reader_filters_without_building() {
+        let mut doc = String::from("users[3]{id,active}:\n");
+        for (id, active) in [(1, true), (2, false), (3, true)] {
+            doc.push_str(&format!("  {id},{active}\n"));
+        }
+        let active = Reader::new(&doc, ParseOptions::default())
+            .filter(|event| matches!(event, Ok((Event::Scalar(Scalar::Bool(true)), _))))
+            .count();
+        assert_eq!(active, 2);
     }
 }
@@ -0,0 +1,260 @@
+use std::{fmt, io};
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, Unexpected,
+    VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use super::{parse, SerdeError, ToonMap, ToonValue};
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Decodes a TOON document into `T`, accepting the shapes [`to_string`]
+/// writes: a one-item list is written as its item and an empty one as an
+/// empty map, so both also read back as sequences.
+///
+/// [`to_string`]: super::to_string
+pub fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, SerdeError> {
+    let value = parse(s).map_err(|e| SerdeError(e.to_string()))?;
+    T::deserialize(ValueDeserializer(value))
+}
+
+/// Like [`from_str`], reading the whole document from `reader` first.
+pub fn from_reader<R: io::Read, T: DeserializeOwned>(mut reader: R) -> Result<T, SerdeError> {
+    let mut input = String::new();
+    reader
+        .read_to_string(&mut input)
+        .map_err(|e| SerdeError(e.to_string()))?;
+    from_str(&input)
+}
+
+/// Hands a parsed value to serde.
+struct ValueDeserializer(ToonValue);
+
+impl<'de> IntoDeserializer<'de, SerdeError> for ValueDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn visit_seq<'de, V: Visitor<'de>>(
+    items: Vec<ToonValue>,
+    visitor: V,
+) -> Result<V::Value, SerdeError> {
+    let mut seq = SeqDeserializer::new(items.into_iter().map(ValueDeserializer));
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+/// Whether `entries` are the items of a list written keyed by index.
+fn is_indexed(entries: &ToonMap) -> bool {
+    entries
+        .keys()
+        .enumerate()
+        .all(|(i, key)| key.parse() == Ok(i))
+}
+
+fn unexpected(value: &ToonValue) -> Unexpected<'_> {
+    match value {
+        ToonValue::Null => Unexpected::Unit,
+        ToonValue::Bool(b) => Unexpected::Bool(*b),
+        ToonValue::Int(i) => Unexpected::Signed(*i),
+        ToonValue::Float(f) => Unexpected::Float(*f),
+        ToonValue::String(s) => Unexpected::Str(s),
+        ToonValue::Array(_) => Unexpected::Seq,
+        ToonValue::Map(_) => Unexpected::Map,
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            ToonValue::Null => visitor.visit_unit(),
+            ToonValue::Bool(b) => visitor.visit_bool(b),
+            ToonValue::Int(i) => visitor.visit_i64(i),
+            ToonValue::Float(f) => visitor.visit_f64(f),
+            ToonValue::String(s) => visitor.visit_string(s),
+            ToonValue::Array(items) => visit_seq(items, visitor),
+            ToonValue::Map(entries) => {
+                let mut map = MapDeserializer::new(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key, ValueDeserializer(value))),
+                );
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            ToonValue::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            ToonValue::Array(items) => visit_seq(items, visitor),
+            ToonValue::Map(entries) if is_indexed(&entries) => {
+                visit_seq(entries.into_values().collect(), visitor)
+            }
+            ToonValue::Map(_) => self.deserialize_any(visitor),
+            item => visit_seq(vec![item], visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            ToonValue::Bool(b) => visitor.visit_string(b.to_string()),
+            ToonValue::Int(i) => visitor.visit_string(i.to_string()),
+            ToonValue::Float(f) => visitor.visit_string(f.to_string()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            ToonValue::String(variant) => visitor.visit_enum(Variant {
+                name: variant,
+                value: None,
+            }),
+            ToonValue::Map(mut entries) if entries.len() == 1 => {
+                let (name, value) = entries.pop().expect("map has one entry");
+                visitor.visit_enum(Variant {
+                    name,
+                    value: Some(value),
+                })
+            }
+            other => Err(de::Error::invalid_type(
+                unexpected(&other),
+                &"an enum variant",
+            )),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 bytes byte_buf
+        unit unit_struct map struct identifier
+    }
+}
+
+/// An enum variant: its name, and its content unless it is a unit variant.
+struct Variant {
+    name: String,
+    value: Option<ToonValue>,
+}
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = SerdeError;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        mut self,
+        seed: S,
+    ) -> Result<(S::Value, Self), SerdeError> {
+        let name = std::mem::take(&mut self.name);
+        let variant = seed.deserialize(name.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl Variant {
+    fn content(self, expected: &str) -> Result<ValueDeserializer, SerdeError> {
+        self.value
+            .map(ValueDeserializer)
+            .ok_or_else(|| de::Error::invalid_type(Unexpected::UnitVariant, &expected))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Variant {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            None | Some(ToonValue::Null) => Ok(()),
+            Some(other) => Err(de::Error::invalid_type(
+                unexpected(&other),
+                &"a unit variant",
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<S::Value, SerdeError> {
+        seed.deserialize(self.content("a newtype variant")?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_seq(self.content("a tuple variant")?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_any(self.content("a struct variant")?, visitor)
+    }
+}
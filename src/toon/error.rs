@@ -0,0 +1,220 @@
+use std::{error, fmt, io};
+
+/// Where an error was found: 1-based line and byte column. A column of 0
+/// means only the line is known. For [`ToonError::InvalidPath`] the span
+/// points into the path instead of the document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+
+    /// The position of `part` in `line`, both slices of the same document.
+    pub(crate) fn within(line_no: usize, line: &str, part: &str) -> Self {
+        let offset = (part.as_ptr() as usize).saturating_sub(line.as_ptr() as usize);
+        Self::new(line_no, offset + 1)
+    }
+
+    /// `offset` bytes further along the same line.
+    pub(crate) fn advance(self, offset: usize) -> Self {
+        Self::new(self.line, self.column + offset)
+    }
+}
+
+/// What a [`ToonError::CountMismatch`] counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountKind {
+    /// Items of an inline array.
+    Items,
+    /// Rows of a tabular array.
+    Rows,
+    /// Cells of a row, against the fields of its header.
+    Fields,
+}
+
+impl fmt::Display for CountKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Items => "items",
+            Self::Rows => "rows",
+            Self::Fields => "fields",
+        })
+    }
+}
+
+/// Errors of the parser, [`RowReader`] and the path functions.
+///
+/// [`RowReader`]: super::RowReader
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ToonError {
+    /// A line indented deeper than its block, in strict mode.
+    UnexpectedIndent { span: Span },
+    /// A line without a `key:`, in strict mode.
+    MissingKey { span: Span },
+    /// An array holding another number of items or rows than its header
+    /// declares (strict mode), or a row with another number of cells than
+    /// its header has fields.
+    CountMismatch {
+        span: Span,
+        kind: CountKind,
+        expected: usize,
+        found: usize,
+    },
+    /// An escape other than `\"`, `\n` and `\\` in a quoted string, in
+    /// strict mode.
+    InvalidEscape { span: Span, escape: char },
+    /// A quoted string without its closing quote, in strict mode.
+    UnterminatedString { span: Span },
+    /// Nesting deeper than [`ParseOptions::max_depth`].
+    ///
+    /// [`ParseOptions::max_depth`]: super::ParseOptions::max_depth
+    DepthExceeded { span: Span, max_depth: usize },
+    /// A key repeated in the same map with [`DuplicateKeys::Error`].
+    ///
+    /// [`DuplicateKeys::Error`]: super::DuplicateKeys::Error
+    DuplicateKey { span: Span, key: String },
+    /// A line after a root array read by [`Reader::from_reader`] that is
+    /// neither blank nor, outside strict mode, without a key.
+    ///
+    /// [`Reader::from_reader`]: super::Reader::from_reader
+    TrailingContent { span: Span },
+    /// A malformed path; `span` points into the path.
+    InvalidPath { span: Span, path: String },
+    /// No tabular array at the path of a [`RowReader`]; `span` is the end of
+    /// the input.
+    ///
+    /// [`RowReader`]: super::RowReader
+    TableNotFound { span: Span, path: String },
+    /// Reading the input failed.
+    Io { span: Span, source: io::Error },
+    /// An error returned by a [`Sink`], which stopped the parser.
+    ///
+    /// [`Sink`]: super::Sink
+    Sink {
+        span: Span,
+        source: Box<dyn error::Error + Send + Sync>,
+    },
+}
+
+impl ToonError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnexpectedIndent { span }
+            | Self::MissingKey { span }
+            | Self::CountMismatch { span, .. }
+            | Self::InvalidEscape { span, .. }
+            | Self::UnterminatedString { span }
+            | Self::DepthExceeded { span, .. }
+            | Self::DuplicateKey { span, .. }
+            | Self::TrailingContent { span }
+            | Self::InvalidPath { span, .. }
+            | Self::TableNotFound { span, .. }
+            | Self::Io { span, .. }
+            | Self::Sink { span, .. } => *span,
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Self::UnexpectedIndent { span }
+            | Self::MissingKey { span }
+            | Self::CountMismatch { span, .. }
+            | Self::InvalidEscape { span, .. }
+            | Self::UnterminatedString { span }
+            | Self::DepthExceeded { span, .. }
+            | Self::DuplicateKey { span, .. }
+            | Self::TrailingContent { span }
+            | Self::InvalidPath { span, .. }
+            | Self::TableNotFound { span, .. }
+            | Self::Io { span, .. }
+            | Self::Sink { span, .. } => span,
+        }
+    }
+
+    /// A [`Sink`] error, for sinks to return their own errors. The parser
+    /// gives it the span of the event the sink failed on.
+    ///
+    /// [`Sink`]: super::Sink
+    pub fn sink(source: impl Into<Box<dyn error::Error + Send + Sync>>) -> Self {
+        Self::Sink {
+            span: Span::default(),
+            source: source.into(),
+        }
+    }
+
+    /// Gives an error raised without knowing its position (sinks only see
+    /// keys and values) `at`, the span of the event the sink failed on.
+    pub(crate) fn located(mut self, at: Span) -> Self {
+        let span = self.span_mut();
+        if span.line == 0 {
+            *span = at;
+        }
+        self
+    }
+}
+
+impl fmt::Display for ToonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedIndent { span } => {
+                write!(f, "Line {}: unexpected indentation", span.line)
+            }
+            Self::MissingKey { span } => write!(f, "Line {}: expected 'key: value'", span.line),
+            Self::CountMismatch {
+                span,
+                kind,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Line {}: expected {expected} {kind}, found {found}",
+                span.line
+            ),
+            Self::InvalidEscape { span, escape } => write!(
+                f,
+                "Line {}, column {}: invalid escape '\\{escape}'",
+                span.line, span.column
+            ),
+            Self::UnterminatedString { span } => write!(
+                f,
+                "Line {}, column {}: unterminated string",
+                span.line, span.column
+            ),
+            Self::DepthExceeded { span, max_depth } => write!(
+                f,
+                "Line {}: parse depth limit of {max_depth} exceeded",
+                span.line
+            ),
+            Self::DuplicateKey { span, key } => {
+                write!(f, "Line {}: duplicate key '{key}'", span.line)
+            }
+            Self::TrailingContent { span } => {
+                write!(
+                    f,
+                    "Line {}: unexpected content after the root array",
+                    span.line
+                )
+            }
+            Self::InvalidPath { path, .. } => write!(f, "Invalid path '{path}'"),
+            Self::TableNotFound { path, .. } => write!(f, "No tabular array at path '{path}'"),
+            Self::Io { source, .. } => source.fmt(f),
+            Self::Sink { source, .. } => source.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ToonError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Sink { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,675 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io;
+
+use super::{
+    emit_value, indent_of, is_root_array, parse_array_header, parse_row_into, ArrayHeader,
+    CountKind, Delimiter, Inline, Lines, ParseOptions, Scalar, Span, ToonError,
+};
+
+/// One step through a document, yielded by [`Reader`] with its [`Span`].
+///
+/// Values nest like the document: a map is `StartMap`, then a `Key` and a
+/// value for each entry, then `End`; a list is `StartArray`, its items, then
+/// `End`. The rows of a tabular array are maps keyed by its fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    StartMap,
+    /// Key of the next value of the innermost map.
+    Key(Cow<'a, str>),
+    /// Opens a list. `len` is the number of items of an inline list, or the
+    /// row count declared by a tabular header (`[N]{...}:`), which is only
+    /// checked in strict mode. `fields` are the fields of a tabular header.
+    StartArray {
+        len: Option<usize>,
+        fields: Option<Vec<Cow<'a, str>>>,
+        delimiter: Delimiter,
+    },
+    Scalar(Scalar<'a>),
+    /// Closes the innermost map or list.
+    End,
+}
+
+impl Event<'_> {
+    /// Copies what is still borrowed, detaching the event from the input.
+    pub fn into_owned(self) -> Event<'static> {
+        match self {
+            Self::StartMap => Event::StartMap,
+            Self::Key(key) => Event::Key(Cow::Owned(key.into_owned())),
+            Self::StartArray {
+                len,
+                fields,
+                delimiter,
+            } => Event::StartArray {
+                len,
+                fields: fields.map(|fields| {
+                    fields
+                        .into_iter()
+                        .map(|field| Cow::Owned(field.into_owned()))
+                        .collect()
+                }),
+                delimiter,
+            },
+            Self::Scalar(value) => Event::Scalar(value.into_owned()),
+            Self::End => Event::End,
+        }
+    }
+}
+
+/// The lines a [`Reader`] reads, without their line endings.
+pub trait Source<'a> {
+    /// The next line, or `None` at the end of the input.
+    fn next_line(&mut self) -> io::Result<Option<Cow<'a, str>>>;
+
+    /// The whole document, for sources holding it in memory. Lets the reader
+    /// look past a root array header to tell whether it is the whole
+    /// document, see [`Reader::from_reader`].
+    fn document(&self) -> Option<&'a str> {
+        None
+    }
+}
+
+/// Lines of a document in memory, borrowed from it.
+#[derive(Debug, Clone)]
+pub struct StrSource<'a> {
+    lines: Lines<'a>,
+    /// Whether the current line of `lines` was handed out.
+    started: bool,
+}
+
+impl<'a> StrSource<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self::at(Lines::new(input))
+    }
+
+    /// Starts at the current line of `lines`.
+    fn at(lines: Lines<'a>) -> Self {
+        Self {
+            lines,
+            started: false,
+        }
+    }
+}
+
+impl<'a> Source<'a> for StrSource<'a> {
+    fn next_line(&mut self) -> io::Result<Option<Cow<'a, str>>> {
+        if self.started {
+            self.lines.advance();
+        }
+        self.started = true;
+        Ok(self.lines.peek().map(Cow::Borrowed))
+    }
+
+    fn document(&self) -> Option<&'a str> {
+        Some(self.lines.input)
+    }
+}
+
+/// Lines read one at a time from a `BufRead`. Lines end at `\n` or `\r\n`,
+/// like with [`StrSource`].
+#[derive(Debug)]
+pub struct IoSource<R> {
+    input: R,
+}
+
+impl<R: io::BufRead> IoSource<R> {
+    pub fn new(input: R) -> Self {
+        Self { input }
+    }
+}
+
+impl<R: io::BufRead> Source<'static> for IoSource<R> {
+    fn next_line(&mut self) -> io::Result<Option<Cow<'static, str>>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(Cow::Owned(line)))
+    }
+}
+
+/// Where reading starts.
+#[derive(Debug, Clone, Copy)]
+enum Start {
+    Document,
+    /// A map whose entries are indented `indent`.
+    Block {
+        indent: usize,
+        depth: usize,
+    },
+    /// An array whose header is the current line.
+    Array {
+        depth: usize,
+    },
+}
+
+/// Something opened on an earlier line and closed by a later one.
+#[derive(Debug)]
+enum Frame<'a> {
+    /// A map whose entries are indented `indent`.
+    Block { indent: usize, depth: usize },
+    /// A key without an inline value: a map follows when the next line is
+    /// indented deeper than the key, else its value is an empty map.
+    Pending {
+        indent: usize,
+        depth: usize,
+        span: Span,
+    },
+    /// The rows of a tabular array, indented deeper than its header.
+    Table {
+        indent: usize,
+        depth: usize,
+        fields: Vec<Cow<'a, str>>,
+        delimiter: Delimiter,
+        len: Option<usize>,
+        rows: usize,
+        span: Span,
+    },
+    /// After a root array, only lines the parser skips may follow.
+    Trailing,
+}
+
+/// What to do with the current line.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// Hand it to the innermost frame.
+    Line,
+    /// The document is the inline value on this line.
+    Value,
+    /// Read the array whose header is this line.
+    Array { depth: usize },
+}
+
+/// Whether a step is done with the current line.
+enum Flow {
+    Advance,
+    Stay,
+}
+
+/// A pull parser yielding the [`Event`]s of a document one at a time, so
+/// documents can be filtered or transformed without building them. Only the
+/// current line and the containers open around it are held in memory.
+///
+/// [`parse_with`](super::parse_with) and friends are built on it and read
+/// documents the same way, with the same errors.
+///
+/// ```
+/// use php_rs_toon::toon::{Event, ParseOptions, Reader};
+///
+/// let input = "users[2]{id,name}:\n  1,Alice\n  2,Bob";
+/// let names: Vec<String> = Reader::new(input, ParseOptions::default())
+///     .map(Result::unwrap)
+///     .filter_map(|(event, _)| match event {
+///         Event::Scalar(value) => Some(format!("{value:?}")),
+///         _ => None,
+///     })
+///     .collect();
+/// assert_eq!(names.len(), 4);
+/// ```
+pub struct Reader<'a, S> {
+    source: S,
+    /// Current line, `None` at the end of the input.
+    line: Option<Cow<'a, str>>,
+    /// The line after it, once [`is_last`](Self::is_last) looked.
+    ahead: Option<Option<Cow<'a, str>>>,
+    /// 1-based number of the current line.
+    number: usize,
+    start: Option<Start>,
+    state: State<'a>,
+    queue: VecDeque<(Event<'a>, Span)>,
+    done: bool,
+}
+
+impl<'a> Reader<'a, StrSource<'a>> {
+    pub fn new(input: &'a str, opts: ParseOptions) -> Self {
+        Self::with_source(StrSource::new(input), opts)
+    }
+
+    /// Reads the map whose entries start at the current line of `lines`,
+    /// indented `indent`.
+    pub(super) fn block(lines: Lines<'a>, indent: usize, depth: usize, opts: ParseOptions) -> Self {
+        Self::starting(lines, Start::Block { indent, depth }, opts)
+    }
+
+    /// Reads the array whose header is the current line of `lines`.
+    pub(super) fn array(lines: Lines<'a>, depth: usize, opts: ParseOptions) -> Self {
+        Self::starting(lines, Start::Array { depth }, opts)
+    }
+
+    fn starting(lines: Lines<'a>, start: Start, opts: ParseOptions) -> Self {
+        let number = lines.number - 1;
+        Self {
+            number,
+            start: Some(start),
+            ..Self::with_source(StrSource::at(lines), opts)
+        }
+    }
+}
+
+impl<R: io::BufRead> Reader<'static, IoSource<R>> {
+    /// Reads a document from `input` line by line, yielding owned events.
+    ///
+    /// Whether a root array header (`[N]: ...`) is the whole document
+    /// depends on what follows it, which a stream cannot tell up front. Here
+    /// the first entry of a document is always read as its root array when
+    /// it has such a header, and entries after the array are a
+    /// [`ToonError::TrailingContent`] instead of making the document a map.
+    pub fn from_reader(input: R, opts: ParseOptions) -> Self {
+        Self::with_source(IoSource::new(input), opts)
+    }
+}
+
+impl<'a, S: Source<'a>> Reader<'a, S> {
+    pub fn with_source(source: S, opts: ParseOptions) -> Self {
+        Self {
+            source,
+            line: None,
+            ahead: None,
+            number: 0,
+            start: Some(Start::Document),
+            state: State {
+                opts,
+                frames: Vec::new(),
+            },
+            queue: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn advance(&mut self) -> Result<(), ToonError> {
+        self.line = match self.ahead.take() {
+            Some(line) => line,
+            None => self.source.next_line().map_err(|e| self.io_error(e))?,
+        };
+        self.number += 1;
+        Ok(())
+    }
+
+    /// Whether the current line is the last one.
+    fn is_last(&mut self) -> Result<bool, ToonError> {
+        if self.ahead.is_none() {
+            let line = self.source.next_line().map_err(|e| self.io_error(e))?;
+            self.ahead = Some(line);
+        }
+        Ok(matches!(self.ahead, Some(None)))
+    }
+
+    fn io_error(&self, source: io::Error) -> ToonError {
+        ToonError::Io {
+            span: Span::new(self.number + 1, 0),
+            source,
+        }
+    }
+
+    /// Reads lines until there are events to hand out or the document ends.
+    fn fill(&mut self) -> Result<(), ToonError> {
+        while self.queue.is_empty() && !self.done {
+            if let Some(start) = self.start.take() {
+                self.advance()?;
+                match start {
+                    Start::Document => self.begin_document()?,
+                    Start::Block { indent, depth } => {
+                        let span = Span::new(self.number, indent + 1);
+                        self.state
+                            .open_block(indent, depth, span, &mut self.queue)?;
+                    }
+                    Start::Array { depth } => self.step(Step::Array { depth })?,
+                }
+            } else if self.state.frames.is_empty() {
+                self.done = true;
+            } else {
+                self.step(Step::Line)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn begin_document(&mut self) -> Result<(), ToonError> {
+        let Some(first) = self.line.as_deref() else {
+            self.queue
+                .push_back((Event::Scalar(Scalar::Null), Span::new(1, 0)));
+            return Ok(());
+        };
+
+        // A single line without a key is a value of its own
+        let line = first.trim();
+        if !line.contains(':') && !line.is_empty() && self.is_last()? {
+            return self.step(Step::Value);
+        }
+
+        // A root array header (`[N]{...}:`) is the whole document
+        let strict = self.state.opts.strict;
+        let root = match self.source.document() {
+            Some(input) => is_root_array(Lines::new(input), &self.state.opts),
+            None => {
+                while let Some(line) = self.line.as_deref() {
+                    if !line.trim().is_empty() && (strict || line.contains(':')) {
+                        break;
+                    }
+                    self.advance()?;
+                }
+                self.line.as_deref().is_some_and(|line| {
+                    line.trim().split_once(':').is_some_and(|(key_part, _)| {
+                        parse_array_header(key_part, self.state.opts.delimiter)
+                            .is_some_and(|header| header.key.is_empty())
+                            && (!strict || indent_of(line) == 0)
+                    })
+                })
+            }
+        };
+        if !root {
+            let span = Span::new(self.number, 1);
+            return self.state.open_block(0, 0, span, &mut self.queue);
+        }
+
+        while self.line.as_deref().is_some_and(|line| !line.contains(':')) {
+            self.advance()?;
+        }
+        self.state.frames.push(Frame::Trailing);
+        self.step(Step::Array { depth: 0 })
+    }
+
+    /// Runs `step` on the current line. Events of an owned line are copied
+    /// out of it, so they outlive the line.
+    fn step(&mut self, step: Step) -> Result<(), ToonError> {
+        let Self {
+            line,
+            number,
+            state,
+            queue,
+            ..
+        } = self;
+        let flow = match line {
+            Some(Cow::Owned(text)) => {
+                let mut events = VecDeque::new();
+                let flow = state.step(step, Some(text), *number, &mut events, |field| {
+                    Cow::Owned(field.into_owned())
+                })?;
+                queue.extend(
+                    events
+                        .into_iter()
+                        .map(|(event, span)| (event.into_owned(), span)),
+                );
+                flow
+            }
+            Some(Cow::Borrowed(text)) => {
+                state.step(step, Some(*text), *number, queue, |field| field)?
+            }
+            None => state.step(step, None, *number, queue, |field| field)?,
+        };
+        match flow {
+            Flow::Advance => self.advance(),
+            Flow::Stay => Ok(()),
+        }
+    }
+}
+
+impl<'a, S: Source<'a>> Iterator for Reader<'a, S> {
+    type Item = Result<(Event<'a>, Span), ToonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            self.done = true;
+            self.queue.clear();
+            self.state.frames.clear();
+            return Some(Err(e));
+        }
+        self.queue.pop_front().map(Ok)
+    }
+}
+
+/// What the reader keeps between lines, apart from the lines themselves.
+struct State<'a> {
+    opts: ParseOptions,
+    frames: Vec<Frame<'a>>,
+}
+
+impl<'a> State<'a> {
+    /// Reads `line`, `None` at the end of the input, into events borrowing
+    /// from it (`'l`). What outlives the line goes through `keep`.
+    fn step<'l>(
+        &mut self,
+        step: Step,
+        line: Option<&'l str>,
+        number: usize,
+        out: &mut VecDeque<(Event<'l>, Span)>,
+        keep: impl Fn(Cow<'l, str>) -> Cow<'a, str>,
+    ) -> Result<Flow, ToonError>
+    where
+        'a: 'l,
+    {
+        let opts = &self.opts;
+        match (step, line) {
+            (Step::Value, Some(line)) => {
+                let value = line.trim();
+                let at = Span::within(number, line, value);
+                emit_value(value, at, opts.delimiter, 0, opts, out)?;
+                return Ok(Flow::Advance);
+            }
+            (Step::Array { depth }, Some(line)) => {
+                let header = line.trim().split_once(':').and_then(|(key_part, inline)| {
+                    parse_array_header(key_part, opts.delimiter).map(|header| (header, inline))
+                });
+                if let Some((header, inline)) = header {
+                    self.array(line, header, inline, depth, number, out, keep)?;
+                }
+                return Ok(Flow::Advance);
+            }
+            _ => {}
+        }
+
+        let Some(frame) = self.frames.last_mut() else {
+            return Ok(Flow::Stay);
+        };
+        match frame {
+            Frame::Block {
+                indent: base,
+                depth,
+            } => {
+                let (base, depth) = (*base, *depth);
+                let Some(line) = line else {
+                    return Ok(self.close(Span::new(number, 0), out));
+                };
+                if line.trim().is_empty() {
+                    return Ok(Flow::Advance);
+                }
+                let indent = indent_of(line);
+                if indent < base {
+                    return Ok(self.close(Span::new(number, 0), out));
+                }
+                let span = Span::new(number, indent + 1);
+                if opts.strict && indent > base {
+                    return Err(ToonError::UnexpectedIndent { span });
+                }
+
+                let Some((key_part, val_part)) = line.trim().split_once(':') else {
+                    if opts.strict {
+                        return Err(ToonError::MissingKey { span });
+                    }
+                    return Ok(Flow::Advance);
+                };
+
+                if let Some(header) = parse_array_header(key_part, opts.delimiter) {
+                    out.push_back((Event::Key(Cow::Borrowed(header.key)), span));
+                    self.array(line, header, val_part, depth + 1, number, out, keep)?;
+                    return Ok(Flow::Advance);
+                }
+
+                out.push_back((Event::Key(Cow::Borrowed(key_part.trim())), span));
+                let val_str = val_part.trim();
+                if val_str.is_empty() {
+                    self.frames.push(Frame::Pending {
+                        indent,
+                        depth: depth + 1,
+                        span: Span::within(number, line, val_part),
+                    });
+                } else {
+                    let at = Span::within(number, line, val_str);
+                    emit_value(val_str, at, opts.delimiter, depth + 1, opts, out)?;
+                }
+                Ok(Flow::Advance)
+            }
+            Frame::Pending {
+                indent,
+                depth,
+                span,
+            } => {
+                let (indent, depth, span) = (*indent, *depth, *span);
+                self.frames.pop();
+                match line {
+                    Some(next) if !next.trim().is_empty() && indent_of(next) > indent => {
+                        let child = indent_of(next);
+                        self.open_block(child, depth, Span::new(number, child + 1), out)?;
+                    }
+                    _ => {
+                        self.check_depth(depth, span)?;
+                        out.push_back((Event::StartMap, span));
+                        out.push_back((Event::End, span));
+                    }
+                }
+                Ok(Flow::Stay)
+            }
+            Frame::Table {
+                indent,
+                depth,
+                fields,
+                delimiter,
+                len,
+                rows,
+                span,
+            } => match line {
+                Some(line) if line.trim().is_empty() => Ok(Flow::Advance),
+                Some(line) if indent_of(line) > *indent => {
+                    parse_row_into(line, fields, *delimiter, number, *depth + 1, opts, out)?;
+                    *rows += 1;
+                    Ok(Flow::Advance)
+                }
+                _ => {
+                    if let Some(len) = len.filter(|len| opts.strict && rows != len) {
+                        return Err(ToonError::CountMismatch {
+                            span: *span,
+                            kind: CountKind::Rows,
+                            expected: len,
+                            found: *rows,
+                        });
+                    }
+                    Ok(self.close(Span::new(number, 0), out))
+                }
+            },
+            Frame::Trailing => match line {
+                Some(line) if line.trim().is_empty() || (!opts.strict && !line.contains(':')) => {
+                    Ok(Flow::Advance)
+                }
+                Some(line) => Err(ToonError::TrailingContent {
+                    span: Span::new(number, indent_of(line) + 1),
+                }),
+                None => {
+                    self.frames.pop();
+                    Ok(Flow::Stay)
+                }
+            },
+        }
+    }
+
+    /// Fails for a map or list at `depth` when that is deeper than allowed.
+    fn check_depth(&self, depth: usize, span: Span) -> Result<(), ToonError> {
+        if depth > self.opts.depth_limit() {
+            return Err(ToonError::DepthExceeded {
+                span,
+                max_depth: self.opts.depth_limit(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Opens the map whose first entry is indented `indent`.
+    fn open_block<'l>(
+        &mut self,
+        indent: usize,
+        depth: usize,
+        span: Span,
+        out: &mut VecDeque<(Event<'l>, Span)>,
+    ) -> Result<(), ToonError> {
+        self.check_depth(depth, span)?;
+        self.frames.push(Frame::Block { indent, depth });
+        out.push_back((Event::StartMap, span));
+        Ok(())
+    }
+
+    /// Closes the innermost map or table, leaving the current line to its
+    /// parent.
+    fn close<'l>(&mut self, span: Span, out: &mut VecDeque<(Event<'l>, Span)>) -> Flow {
+        self.frames.pop();
+        out.push_back((Event::End, span));
+        Flow::Stay
+    }
+
+    /// Reads the array whose header is `line`: all of an inline one, or the
+    /// start of a tabular one, whose rows are the next lines. `depth` is the
+    /// depth of the array.
+    #[allow(clippy::too_many_arguments)]
+    fn array<'l>(
+        &mut self,
+        line: &'l str,
+        header: ArrayHeader<'l>,
+        inline: &'l str,
+        depth: usize,
+        number: usize,
+        out: &mut VecDeque<(Event<'l>, Span)>,
+        keep: impl Fn(Cow<'l, str>) -> Cow<'a, str>,
+    ) -> Result<(), ToonError> {
+        let indent = indent_of(line);
+        let span = Span::new(number, indent + 1);
+        // The rows of a table are maps one level below it
+        self.check_depth(depth + usize::from(header.fields.is_some()), span)?;
+        let opts = &self.opts;
+
+        let Some(fields) = header.fields else {
+            let at = Span::within(number, line, inline);
+            let items = Inline::new(inline, at);
+            let parts = items.parts(0, inline.len(), header.delimiter);
+            if let Some(len) = header.len.filter(|len| opts.strict && parts.len() != *len) {
+                return Err(ToonError::CountMismatch {
+                    span,
+                    kind: CountKind::Items,
+                    expected: len,
+                    found: parts.len(),
+                });
+            }
+            let start = Event::StartArray {
+                len: Some(parts.len()),
+                fields: None,
+                delimiter: header.delimiter,
+            };
+            out.push_back((start, span));
+            for (start, end) in parts {
+                items.emit(start, end, header.delimiter, depth + 1, opts, out)?;
+            }
+            out.push_back((Event::End, at.advance(inline.len())));
+            return Ok(());
+        };
+
+        let start = Event::StartArray {
+            len: header.len,
+            fields: Some(fields.clone()),
+            delimiter: header.delimiter,
+        };
+        out.push_back((start, span));
+        self.frames.push(Frame::Table {
+            indent,
+            depth,
+            fields: fields.into_iter().map(keep).collect(),
+            delimiter: header.delimiter,
+            len: header.len,
+            rows: 0,
+            span,
+        });
+        Ok(())
+    }
+}
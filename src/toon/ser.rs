@@ -0,0 +1,392 @@
+use std::{fmt, io};
+
+use serde::ser::{self, Serialize};
+
+use super::{encode, encode_to_writer, EncodeOptions, ToonMap, ToonValue};
+
+/// Error of the serde support: a value TOON cannot represent, a document
+/// that does not parse, or one that does not fit the requested type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerdeError(pub(super) String);
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Encodes `value` as a TOON document. Structs and maps become maps,
+/// sequences of structs with the same primitive fields become tables,
+/// `None` and `()` become `null`, and enums are written like serde_json
+/// does: unit variants as their name, others as a map from the name to
+/// their content.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, SerdeError> {
+    Ok(encode(&value.serialize(ValueSerializer)?))
+}
+
+/// Like [`to_string`], streaming the document into `writer`.
+pub fn to_writer<W: io::Write, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+) -> Result<(), SerdeError> {
+    let value = value.serialize(ValueSerializer)?;
+    encode_to_writer(&value, &EncodeOptions::default(), writer)
+        .map_err(|e| SerdeError(e.to_string()))
+}
+
+/// Builds the `ToonValue` of a serializable value.
+struct ValueSerializer;
+
+fn int<T: TryInto<i64> + fmt::Display + Copy>(v: T) -> Result<ToonValue, SerdeError> {
+    v.try_into()
+        .map(ToonValue::Int)
+        .map_err(|_| SerdeError(format!("Integer {v} does not fit in 64 bits")))
+}
+
+/// `value` under the name of its enum variant, if any.
+fn tagged(variant: Option<&'static str>, value: ToonValue) -> ToonValue {
+    match variant {
+        Some(name) => ToonValue::Map(ToonMap::from([(name.to_string(), value)])),
+        None => value,
+    }
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<ToonValue, SerdeError> {
+        int(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::Array(
+            v.iter().map(|&b| ToonValue::Int(b.into())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<ToonValue, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<ToonValue, SerdeError> {
+        Ok(ToonValue::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<ToonValue, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<ToonValue, SerdeError> {
+        Ok(tagged(Some(variant), value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer {
+            entries: ToonMap::with_capacity(len.unwrap_or(0)),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer {
+            entries: ToonMap::with_capacity(len),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+struct SeqSerializer {
+    items: Vec<ToonValue>,
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ToonValue, SerdeError> {
+        Ok(tagged(self.variant, ToonValue::Array(self.items)))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<ToonValue, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<ToonValue, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<ToonValue, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<ToonValue, SerdeError> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    entries: ToonMap,
+    /// Key of the value `serialize_value` is about to receive.
+    key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn finish(self) -> Result<ToonValue, SerdeError> {
+        Ok(tagged(self.variant, ToonValue::Map(self.entries)))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        // Keys are written as text, so only values that read back the same
+        // way are accepted
+        let key = match key.serialize(ValueSerializer)? {
+            ToonValue::String(s) => s,
+            ToonValue::Int(i) => i.to_string(),
+            ToonValue::Bool(b) => b.to_string(),
+            other => {
+                return Err(SerdeError(format!(
+                    "Map keys must be strings, integers or booleans, got {other:?}"
+                )))
+            }
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self.key.take().unwrap_or_default();
+        self.entries.insert(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<ToonValue, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.entries
+            .insert(key.to_string(), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<ToonValue, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = ToonValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<ToonValue, SerdeError> {
+        self.finish()
+    }
+}
@@ -0,0 +1,279 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Index;
+use std::str::FromStr;
+
+use super::{encode, parse, ToonError, ToonMap, ToonValue};
+
+/// Returned by the `Index` impls for missing keys and out-of-range indexes.
+static NULL: ToonValue = ToonValue::Null;
+
+impl ToonValue {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Floats, and integers converted to `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(i) => Some(*i as f64),
+            Self::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<ToonValue>> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<ToonValue>> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&ToonMap> {
+        match self {
+            Self::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_map_mut(&mut self) -> Option<&mut ToonMap> {
+        match self {
+            Self::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// The value under `key` when `self` is a map.
+    pub fn get(&self, key: &str) -> Option<&ToonValue> {
+        self.as_map()?.get(key)
+    }
+
+    /// Mutable counterpart of [`get`](Self::get).
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut ToonValue> {
+        self.as_map_mut()?.get_mut(key)
+    }
+}
+
+/// `value["key"]` reads a map entry, yielding `Null` when the key is missing
+/// or `value` is not a map, so lookups can be chained.
+impl Index<&str> for ToonValue {
+    type Output = ToonValue;
+
+    fn index(&self, key: &str) -> &ToonValue {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// `value[i]` reads a list item, yielding `Null` when `i` is out of range or
+/// `value` is not a list.
+impl Index<usize> for ToonValue {
+    type Output = ToonValue;
+
+    fn index(&self, index: usize) -> &ToonValue {
+        self.as_array()
+            .and_then(|items| items.get(index))
+            .unwrap_or(&NULL)
+    }
+}
+
+macro_rules! from_int {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for ToonValue {
+                fn from(value: $ty) -> Self {
+                    Self::Int(value.into())
+                }
+            }
+        )*
+    };
+}
+
+from_int!(i8, i16, i32, i64, u8, u16, u32);
+
+impl From<bool> for ToonValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<f32> for ToonValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value.into())
+    }
+}
+
+impl From<f64> for ToonValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<&str> for ToonValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for ToonValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<Cow<'_, str>> for ToonValue {
+    fn from(value: Cow<'_, str>) -> Self {
+        Self::String(value.into_owned())
+    }
+}
+
+/// `None` becomes `Null`.
+impl<T: Into<ToonValue>> From<Option<T>> for ToonValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl<T: Into<ToonValue>> From<Vec<T>> for ToonValue {
+    fn from(items: Vec<T>) -> Self {
+        Self::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl FromIterator<ToonValue> for ToonValue {
+    fn from_iter<I: IntoIterator<Item = ToonValue>>(iter: I) -> Self {
+        Self::Array(iter.into_iter().collect())
+    }
+}
+
+/// Collects key/value pairs into a map, keeping their order. A repeated key
+/// keeps its first position and its last value.
+impl<K: Into<String>, V: Into<ToonValue>> FromIterator<(K, V)> for ToonValue {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self::Map(
+            iter.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Writes the value as a TOON document with the default [`EncodeOptions`].
+///
+/// [`EncodeOptions`]: super::EncodeOptions
+impl fmt::Display for ToonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&encode(self))
+    }
+}
+
+/// Parses a TOON document with the default [`ParseOptions`].
+///
+/// [`ParseOptions`]: super::ParseOptions
+impl FromStr for ToonValue {
+    type Err = ToonError;
+
+    fn from_str(s: &str) -> Result<Self, ToonError> {
+        parse(s)
+    }
+}
+
+/// Builds a [`ToonValue`](crate::toon::ToonValue) from a JSON-like literal.
+/// `null`, `[...]` and `{...}` are taken literally and anything else goes
+/// through `ToonValue::from`, so variables and expressions can be mixed in.
+/// Map keys are string literals or any single token tree convertible into
+/// a `String`, such as a variable or a parenthesized expression.
+///
+/// ```
+/// use php_rs_toon::toon;
+///
+/// let name = "Alice";
+/// let user = toon!({
+///     "id": 1,
+///     "name": name,
+///     "tags": ["admin", "ops"],
+///     "manager": null,
+/// });
+/// assert_eq!(user["tags"][1].as_str(), Some("ops"));
+/// ```
+#[macro_export]
+macro_rules! toon {
+    // Lists and maps are munched one item at a time, so nested literals and
+    // plain expressions can be mixed
+    (@list [$($done:expr,)*]) => {
+        $crate::toon::ToonValue::Array(vec![$($done,)*])
+    };
+    (@list [$($done:expr,)*] null $(, $($rest:tt)*)?) => {
+        $crate::toon!(@list [$($done,)* $crate::toon::ToonValue::Null,] $($($rest)*)?)
+    };
+    (@list [$($done:expr,)*] [$($inner:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::toon!(@list [$($done,)* $crate::toon!([$($inner)*]),] $($($rest)*)?)
+    };
+    (@list [$($done:expr,)*] {$($inner:tt)*} $(, $($rest:tt)*)?) => {
+        $crate::toon!(@list [$($done,)* $crate::toon!({$($inner)*}),] $($($rest)*)?)
+    };
+    (@list [$($done:expr,)*] $next:expr $(, $($rest:tt)*)?) => {
+        $crate::toon!(@list [$($done,)* $crate::toon!($next),] $($($rest)*)?)
+    };
+
+    (@map [$($done:expr,)*]) => {
+        $crate::toon::ToonValue::Map(
+            <$crate::toon::ToonMap as ::std::iter::FromIterator<_>>::from_iter([$($done,)*])
+        )
+    };
+    (@map [$($done:expr,)*] $key:tt : null $(, $($rest:tt)*)?) => {
+        $crate::toon!(@map [$($done,)* $crate::toon!(@entry $key, $crate::toon::ToonValue::Null),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : [$($inner:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::toon!(@map [$($done,)* $crate::toon!(@entry $key, $crate::toon!([$($inner)*])),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : {$($inner:tt)*} $(, $($rest:tt)*)?) => {
+        $crate::toon!(@map [$($done,)* $crate::toon!(@entry $key, $crate::toon!({$($inner)*})),] $($($rest)*)?)
+    };
+    (@map [$($done:expr,)*] $key:tt : $value:expr $(, $($rest:tt)*)?) => {
+        $crate::toon!(@map [$($done,)* $crate::toon!(@entry $key, $crate::toon!($value)),] $($($rest)*)?)
+    };
+    (@entry $key:tt, $value:expr) => {
+        (::std::string::String::from($key), $value)
+    };
+
+    (null) => {
+        $crate::toon::ToonValue::Null
+    };
+    ([$($items:tt)*]) => {
+        $crate::toon!(@list [] $($items)*)
+    };
+    ({$($entries:tt)*}) => {
+        $crate::toon!(@map [] $($entries)*)
+    };
+    ($other:expr) => {
+        $crate::toon::ToonValue::from($other)
+    };
+}
@@ -32,11 +32,11 @@ class ToonTestRunner {
         echo "\n";
     }
 
-    public function test_encode_decode($name, $toon_string, $expected_data = null) {
+    public function test_encode_decode($name, $toon_string, $expected_data = null, array $options = []) {
         $this->totalTests++;
         echo "--- Testing Decode: $name ---\n";
         try {
-            $decoded = toon_decode($toon_string);
+            $decoded = toon_decode($toon_string, $options);
 
             if ($expected_data !== null && $decoded !== $expected_data) {
                 echo "❌ FAIL\n";
@@ -101,6 +101,33 @@ function build_deep_nested_array(int $depth): array {
     return ["root" => $value];
 }
 
+// Rust allocations are invisible to memory_get_usage(), so memory tests
+// read the high-water mark of the process after resetting it. Only Linux
+// allows the reset; reset_peak_rss() returns false elsewhere.
+function reset_peak_rss(): bool {
+    return @file_put_contents("/proc/self/clear_refs", "5") !== false;
+}
+
+function peak_rss(): int {
+    preg_match('/VmHWM:\s+(\d+) kB/', file_get_contents("/proc/self/status"), $m);
+    return (int) $m[1] * 1024;
+}
+
+enum OrderStatus: string {
+    case Pending = 'pending';
+    case Shipped = 'shipped';
+}
+
+enum Priority: int {
+    case Low = 1;
+    case High = 2;
+}
+
+enum Suit {
+    case Hearts;
+    case Spades;
+}
+
 $tester = new ToonTestRunner();
 
 echo "╔════════════════════════════════════════════════════════════════╗\n";
@@ -139,12 +166,16 @@ $tester->test_round_trip("Float Edge Cases", [
     "small_decimal" => 0.00001
 ]);
 
-// 1.4 String Types (Note: numeric-looking strings parse as integers)
+// 1.4 String Types (strings that look like numbers, booleans or null are quoted)
 $tester->test_round_trip("String Types", [
     "simple" => "hello",
     "with_spaces" => "hello world",
     "empty_string" => "",
-    "quoted_numeric" => "abc123"
+    "quoted_numeric" => "abc123",
+    "numeric_string" => "007",
+    "float_string" => "3.0",
+    "null_string" => "null",
+    "bool_string" => "true"
 ]);
 
 // 1.5 Boolean and Null
@@ -482,6 +513,1362 @@ $tester->test_encode("Encode Nested", [
     ]
 ]);
 
+// ============================================================================
+// SECTION 9: ENUMS
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 9: ENUMS\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 9.1 Encode enum cases
+$tester->test_encode("Encode Enum Cases", [
+    "status" => OrderStatus::Shipped,
+    "priority" => Priority::High,
+    "suit" => Suit::Hearts
+], "status: shipped\npriority: 2\nsuit: Hearts");
+
+// 9.2 Decode fields into enums
+$tester->test_encode_decode("Decode Enum Fields",
+    "order:\n  status: pending\n  priority: 1\n  suit: Spades",
+    [
+        "order" => [
+            "status" => OrderStatus::Pending,
+            "priority" => Priority::Low,
+            "suit" => Suit::Spades
+        ]
+    ],
+    ["enums" => ["status" => OrderStatus::class, "order.priority" => Priority::class, "suit" => Suit::class]]
+);
+
+// 9.3 Unknown backing value
+echo "--- Testing: Unknown Enum Value ---\n";
+$tester->totalTests++;
+try {
+    toon_decode("status: lost", ["enums" => ["status" => OrderStatus::class]]);
+    echo "❌ FAIL - Expected ValueError not thrown\n";
+    $tester->failedTests++;
+} catch (ValueError $e) {
+    echo "✅ PASS - Caught expected error: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 10: DATES
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 10: DATES\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+$createdAt = new DateTimeImmutable("2024-05-01T12:30:00+00:00");
+
+// 10.1 Encode DateTimeInterface as ISO 8601
+$tester->test_encode("Encode Date (ISO 8601)", [
+    "created" => $createdAt
+], "created: \"2024-05-01T12:30:00+00:00\"");
+
+// 10.2 Encode with a custom format
+$tester->totalTests++;
+echo "--- Testing Encode: Date Format Option ---\n";
+$encoded = toon_encode(["day" => new DateTime("2024-05-01")], ["date_format" => "Y-m-d"]);
+if ($encoded === "day: 2024-05-01") {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got:\n$encoded\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 10.3 Decode ISO 8601 strings everywhere / under listed paths
+$tester->totalTests++;
+echo "--- Testing Decode: Date Decoding ---\n";
+$toon = "created: \"2024-05-01T12:30:00+00:00\"\nmeta:\n  updated: 2024-05-02\n  note: 2024-05-03";
+$everywhere = toon_decode($toon, ["dates" => true]);
+$scoped = toon_decode($toon, ["dates" => ["meta.updated"]]);
+$plain = toon_decode($toon);
+if ($everywhere["created"] instanceof DateTimeImmutable
+    && $everywhere["created"] == $createdAt
+    && $everywhere["meta"]["note"] instanceof DateTimeImmutable
+    && is_string($scoped["created"])
+    && $scoped["meta"]["updated"] instanceof DateTimeImmutable
+    && is_string($scoped["meta"]["note"])
+    && is_string($plain["meta"]["updated"])) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    var_dump($everywhere, $scoped);
+    $tester->failedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 11: REFERENCES & CYCLES
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 11: REFERENCES & CYCLES\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 11.1 PHP references are dereferenced
+$name = "Alice";
+$withRef = ["user" => ["name" => &$name, "tags" => ["a", "b"]]];
+$tester->test_encode("Encode References", $withRef, "user:\n  name: Alice\n  tags: a, b");
+
+// 11.2 Shared (non-circular) values are fine
+$shared = ["id" => 7];
+$tester->test_encode("Encode Shared Values", ["a" => $shared, "b" => $shared], "a:\n  id: 7\nb:\n  id: 7");
+
+// 11.3 Self-referencing array
+echo "--- Testing: Circular Array ---\n";
+$tester->totalTests++;
+$loop = ["a" => ["b" => []]];
+$loop["a"]["b"]["c"] = &$loop;
+try {
+    toon_encode($loop);
+    echo "❌ FAIL - Expected circular reference exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    if (str_contains($e->getMessage(), "Circular reference at path a.b.c")) {
+        echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Unexpected message: " . $e->getMessage() . "\n";
+        $tester->failedTests++;
+    }
+}
+echo "\n";
+
+// 11.4 Self-referencing Traversable
+echo "--- Testing: Circular Traversable ---\n";
+$tester->totalTests++;
+$bag = new ArrayObject();
+$bag["self"] = $bag;
+try {
+    toon_encode(["root" => $bag]);
+    echo "❌ FAIL - Expected circular reference exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    if (str_contains($e->getMessage(), "Circular reference at path root.self")) {
+        echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Unexpected message: " . $e->getMessage() . "\n";
+        $tester->failedTests++;
+    }
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 12: UNSUPPORTED TYPES
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 12: UNSUPPORTED TYPES\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+$handle = fopen("php://memory", "r");
+$unsupported = [
+    "name" => "report",
+    "file" => $handle,
+    "hooks" => ["done" => fn() => true]
+];
+
+// 12.1 Default policy throws
+echo "--- Testing: Unsupported Types Throw ---\n";
+$tester->totalTests++;
+try {
+    toon_encode($unsupported);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 12.2 Substitute null and record paths
+$tester->totalTests++;
+echo "--- Testing: Unsupported Types as Null ---\n";
+$encoded = toon_encode($unsupported, ["unsupported" => "null"]);
+if ($encoded === "name: report\nfile: null\nhooks:\n  done: null"
+    && toon_last_substitutions() === ["file", "hooks.done"]) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got:\n$encoded\n";
+    var_dump(toon_last_substitutions());
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 12.3 User-supplied converter
+$tester->totalTests++;
+echo "--- Testing: Unsupported Types Converter ---\n";
+$encoded = toon_encode($unsupported, [
+    "unsupported" => fn($value, string $path) => is_resource($value) ? get_resource_type($value) : "callable:$path"
+]);
+if ($encoded === "name: report\nfile: stream\nhooks:\n  done: \"callable:hooks.done\""
+    && toon_last_substitutions() === ["file", "hooks.done"]) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got:\n$encoded\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 12.4 A clean encode resets the recorded paths
+$tester->totalTests++;
+echo "--- Testing: Substitutions Reset ---\n";
+toon_encode(["ok" => true]);
+if (toon_last_substitutions() === []) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+fclose($handle);
+
+// ============================================================================
+// SECTION 13: INI SETTINGS AND FORMAT OPTIONS
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 13: INI SETTINGS AND FORMAT OPTIONS\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+$formatData = ["user" => ["tags" => ["a", "b"]]];
+
+// 13.1 INI defaults apply when no options are given
+$tester->totalTests++;
+echo "--- Testing: INI Defaults ---\n";
+ini_set("toon.indent", "4");
+ini_set("toon.delimiter", "pipe");
+$encoded = toon_encode($formatData);
+$decoded = toon_decode($encoded);
+ini_restore("toon.indent");
+ini_restore("toon.delimiter");
+if ($encoded === "user:\n    tags: a|b" && $decoded === $formatData) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got:\n$encoded\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 13.2 Explicit options override INI
+$tester->totalTests++;
+echo "--- Testing: Options Override INI ---\n";
+ini_set("toon.indent", "4");
+$encoded = toon_encode($formatData, ["indent" => 1, "delimiter" => "\t"]);
+ini_restore("toon.indent");
+if ($encoded === "user:\n tags: a\tb") {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got:\n$encoded\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 13.3 Strict Mode Rejects Lines Without Key
+echo "--- Testing: Strict Mode Rejects Lines Without Key ---\n";
+$tester->totalTests++;
+try {
+    toon_decode("a: 1\njunk", ["strict" => true]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 13.4 Input Size Limit
+echo "--- Testing: Input Size Limit ---\n";
+$tester->totalTests++;
+ini_set("toon.max_input_size", "8");
+try {
+    toon_decode("name: longer than eight bytes");
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+ini_restore("toon.max_input_size");
+echo "\n";
+
+// 13.5 Custom Max Depth
+echo "--- Testing: Custom Max Depth ---\n";
+$tester->totalTests++;
+try {
+    toon_encode(["a" => ["b" => ["c" => 1]]], ["max_depth" => 1]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 13.6 Max depth above the default encoder limit of 100
+echo "--- Testing: Max Depth Above 100 ---\n";
+$tester->totalTests++;
+$deep = "x";
+for ($i = 0; $i < 150; $i++) {
+    $deep = ["k" => $deep];
+}
+try {
+    $encoded = toon_encode($deep, ["max_depth" => 500]);
+    if (!str_contains($encoded, "[MaxDepthExceeded]")
+        && toon_decode($encoded, ["max_depth" => 500]) === $deep) {
+        echo "✅ PASS\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Output was truncated\n";
+        $tester->failedTests++;
+    }
+} catch (Exception $e) {
+    echo "❌ FAIL - " . $e->getMessage() . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 14: INTROSPECTION
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 14: INTROSPECTION\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 14.1 Version and features
+$tester->totalTests++;
+echo "--- Testing: Version and Features ---\n";
+$version = toon_version();
+$features = toon_features();
+if (preg_match('/^\d+\.\d+\.\d+/', $version)
+    && in_array("enums", $features, true)) {
+    echo "✅ PASS - $version: " . implode(", ", $features) . "\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got $version\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 14.2 phpinfo() section
+$tester->totalTests++;
+echo "--- Testing: phpinfo() Section ---\n";
+ob_start();
+phpinfo(INFO_MODULES);
+$info = ob_get_clean();
+if (str_contains($info, "toon support") && str_contains($info, "toon.max_depth")) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 15: ENCODER AND DECODER CLASSES
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 15: ENCODER AND DECODER CLASSES\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 15.1 with*() returns a configured copy
+$tester->totalTests++;
+echo "--- Testing: Immutable Encoder ---\n";
+$encoder = new Toon\Encoder();
+$wide = $encoder->withIndent(4)->withDelimiter("|");
+$data = ["user" => ["tags" => ["a", "b"]]];
+if ($encoder->encode($data) === "user:\n  tags: a, b"
+    && $wide->encode($data) === "user:\n    tags: a|b"
+    && $wide !== $encoder) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got:\n" . $wide->encode($data) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 15.2 Decoder object mode and round trip
+$tester->totalTests++;
+echo "--- Testing: Decoder Object Mode ---\n";
+$decoder = (new Toon\Decoder(["delimiter" => "|"]))->withObjectMode(true);
+$decoded = $decoder->decode($wide->encode($data));
+if ($decoded instanceof stdClass
+    && $decoded->user instanceof stdClass
+    && $decoded->user->tags === ["a", "b"]) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    var_dump($decoded);
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 15.3 Strict decoder
+echo "--- Testing: Strict Decoder ---\n";
+$tester->totalTests++;
+try {
+    (new Toon\Decoder())->withStrict(true)->decode("a: 1\n    b: 2");
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 15.4 Invalid configuration is rejected up front
+echo "--- Testing: Invalid Encoder Option ---\n";
+$tester->totalTests++;
+try {
+    new Toon\Encoder(["strict" => true]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 16: FILES AND STREAMS
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 16: FILES AND STREAMS\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+$fileData = ["user" => ["id" => 7, "tags" => ["a", "b"]]];
+$path = tempnam(sys_get_temp_dir(), "toon");
+
+// 16.1 Small file
+$tester->totalTests++;
+echo "--- Testing: Decode File ---\n";
+file_put_contents($path, toon_encode($fileData));
+if (toon_decode_file($path) === $fileData) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 16.2 Large file (memory-mapped)
+$tester->totalTests++;
+echo "--- Testing: Decode Large File ---\n";
+$largeData = [];
+for ($i = 0; $i < 40000; $i++) {
+    $largeData["key$i"] = "value number $i";
+}
+file_put_contents($path, toon_encode($largeData));
+$decoded = toon_decode_file($path);
+if (filesize($path) > 1024 * 1024 && $decoded === $largeData) {
+    echo "✅ PASS - " . filesize($path) . " bytes\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 16.3 Stream wrapper path
+$tester->totalTests++;
+echo "--- Testing: Decode Compressed File ---\n";
+if (extension_loaded("zlib")) {
+    file_put_contents("compress.zlib://$path", toon_encode($fileData));
+    if (toon_decode_file("compress.zlib://$path") === $fileData) {
+        echo "✅ PASS\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL\n";
+        $tester->failedTests++;
+    }
+} else {
+    echo "✅ PASS - zlib not available, skipped\n";
+    $tester->passedTests++;
+}
+echo "\n";
+unlink($path);
+
+// 16.4 PHP stream resource
+$tester->totalTests++;
+echo "--- Testing: Decode Stream ---\n";
+$stream = fopen("php://memory", "w+");
+fwrite($stream, toon_encode($fileData));
+rewind($stream);
+if (toon_decode_stream($stream) === $fileData) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+fclose($stream);
+echo "\n";
+
+// 16.5 Missing file
+echo "--- Testing: Decode Missing File ---\n";
+$tester->totalTests++;
+try {
+    toon_decode_file("/nonexistent/file.toon");
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 17: ENCODE TO STREAM
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 17: ENCODE TO STREAM\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 17.1 Output matches toon_encode()
+$tester->totalTests++;
+echo "--- Testing: Encode To Stream ---\n";
+$streamData = [
+    "name" => "Ada",
+    "address" => ["city" => "London", "zip" => null],
+    "tags" => [],
+];
+$stream = fopen("php://memory", "w+");
+$written = toon_encode_to_stream($streamData, $stream, ["indent" => 4]);
+rewind($stream);
+$output = stream_get_contents($stream);
+fclose($stream);
+$expected = toon_encode($streamData, ["indent" => 4]);
+if ($output === $expected && $written === strlen($expected)) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got:\n$output\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 17.2 Large output round trip through a file
+$tester->totalTests++;
+echo "--- Testing: Encode Large Data To File ---\n";
+$path = tempnam(sys_get_temp_dir(), "toon");
+$stream = fopen($path, "w");
+toon_encode_to_stream($largeData, $stream);
+fclose($stream);
+if (toon_decode_file($path) === $largeData) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+unlink($path);
+echo "\n";
+
+// 17.3 Peak memory does not grow with a copy of the value or the output
+$tester->totalTests++;
+echo "--- Testing: Encode To Stream Memory ---\n";
+if (reset_peak_rss()) {
+    $bigRows = [];
+    for ($i = 0; $i < 200000; $i++) {
+        $bigRows[] = ["id" => $i, "label" => "row-$i", "active" => $i % 2 === 0];
+    }
+    $stream = fopen("/dev/null", "w");
+    reset_peak_rss();
+    $before = peak_rss();
+    $written = toon_encode_to_stream(["rows" => $bigRows], $stream);
+    $growth = peak_rss() - $before;
+    fclose($stream);
+    unset($bigRows);
+    // The row slots of the plan take about 5 MB; a ToonValue copy of the
+    // rows or the text of the document would take several times that
+    if ($written > 5 * 1024 * 1024 && $growth < 16 * 1024 * 1024) {
+        echo "✅ PASS - $written bytes written, peak grew by $growth bytes\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - $written bytes written, peak grew by $growth bytes\n";
+        $tester->failedTests++;
+    }
+} else {
+    echo "✅ PASS - /proc/self/clear_refs not available, skipped\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 18: TABULAR ARRAYS AND ROW ITERATION
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 18: TABULAR ARRAYS AND ROW ITERATION\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+$tabular = "export:\n  users[3]{id,name,active}:\n    1,Ada,true\n    2,\"Bob, Jr\",false\n    3,Cy,true\n  total: 3";
+$expectedRows = [
+    ["id" => 1, "name" => "Ada", "active" => true],
+    ["id" => 2, "name" => "Bob, Jr", "active" => false],
+    ["id" => 3, "name" => "Cy", "active" => true],
+];
+
+// 18.1 toon_decode() understands tabular arrays
+$tester->test_encode_decode("Decode Tabular Array", $tabular, [
+    "export" => ["users" => $expectedRows, "total" => 3]
+]);
+
+// 18.2 Row iteration over a string
+$tester->totalTests++;
+echo "--- Testing: Decode Rows From String ---\n";
+$rows = toon_decode_rows($tabular, "export.users");
+if ($rows instanceof Generator && iterator_to_array($rows) === $expectedRows) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 18.3 Row iteration over a large stream
+$tester->totalTests++;
+echo "--- Testing: Decode Rows From Stream ---\n";
+$stream = fopen("php://temp", "w+");
+fwrite($stream, "rows[100000]{id,label}:\n");
+for ($i = 0; $i < 100000; $i++) {
+    fwrite($stream, "  $i,row-$i\n");
+}
+rewind($stream);
+$count = 0;
+$ok = true;
+$before = memory_get_usage();
+foreach (toon_decode_rows($stream, "rows") as $index => $row) {
+    $ok = $ok && $index === $count && $row === ["id" => $count, "label" => "row-$count"];
+    $count++;
+}
+$growth = memory_get_usage() - $before;
+fclose($stream);
+if ($ok && $count === 100000 && $growth < 1024 * 1024) {
+    echo "✅ PASS - $count rows\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - $count rows, memory grew by $growth bytes\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 18.4 Missing path
+echo "--- Testing: Decode Rows Missing Path ---\n";
+$tester->totalTests++;
+try {
+    foreach (toon_decode_rows($tabular, "export.orders") as $row) {
+    }
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 18.5 Paths use the toon_decode_path() syntax
+$tester->totalTests++;
+echo "--- Testing: Decode Rows Bracket Path ---\n";
+$nested = "teams:\n  0:\n    members[2]{id}:\n      1\n      2";
+$ids = array_column(iterator_to_array(toon_decode_rows($nested, "teams[0].members")), "id");
+if ($ids === [1, 2]) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode($ids) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 18.6 Strict mode checks the declared row count
+$tester->totalTests++;
+echo "--- Testing: Decode Rows Strict Count ---\n";
+$seen = 0;
+try {
+    foreach (toon_decode_rows("r[5]{a}:\n  1\n  2", "r", ["strict" => true]) as $row) {
+        $seen++;
+    }
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    if ($seen === 2 && $e->getCode() === TOON_ERROR_COUNT_MISMATCH) {
+        echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Got: " . $e->getMessage() . "\n";
+        $tester->failedTests++;
+    }
+}
+echo "\n";
+
+// 18.7 String sources are read in place, not copied
+$tester->totalTests++;
+echo "--- Testing: Decode Rows From Large String ---\n";
+if (reset_peak_rss()) {
+    $bigToon = "rows[200000]{id,label}:\n";
+    for ($i = 0; $i < 200000; $i++) {
+        $bigToon .= "  $i,row-$i\n";
+    }
+    $count = 0;
+    reset_peak_rss();
+    $before = peak_rss();
+    foreach (toon_decode_rows($bigToon, "rows") as $row) {
+        $count++;
+    }
+    $growth = peak_rss() - $before;
+    // A copy of the string would add its whole length
+    if ($count === 200000 && $growth < strlen($bigToon) / 2) {
+        echo "✅ PASS - " . strlen($bigToon) . " bytes, peak grew by $growth bytes\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - $count rows, peak grew by $growth bytes\n";
+        $tester->failedTests++;
+    }
+    unset($bigToon);
+} else {
+    echo "✅ PASS - /proc/self/clear_refs not available, skipped\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 18.8 Toon\RowIterator is internal: final, with no constructor
+$tester->totalTests++;
+echo "--- Testing: Row Iterator Is Internal ---\n";
+$class = new ReflectionClass(Toon\RowIterator::class);
+$first = iterator_to_array(toon_decode_rows($tabular, "export.users"));
+$second = iterator_to_array(toon_decode_rows($tabular, "export.users"));
+try {
+    new Toon\RowIterator();
+    $constructed = true;
+} catch (Throwable $e) {
+    $constructed = false;
+}
+if ($class->isFinal() && !$constructed && $first === $expectedRows && $second === $expectedRows) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 19: TRAVERSABLE SOURCES
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 19: TRAVERSABLE SOURCES\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+function toon_test_rows(array $rows): Generator {
+    foreach ($rows as $row) {
+        yield $row;
+    }
+}
+
+$sourceRows = [
+    ["id" => 1, "name" => "Ada"],
+    ["name" => "Bob", "id" => 2],
+    ["id" => 3],
+];
+
+// 19.1 A generator encodes as a table, reordered to the first row's fields
+$tester->totalTests++;
+echo "--- Testing: Encode Generator As Table ---\n";
+$encoded = toon_encode(["users" => toon_test_rows(array_slice($sourceRows, 0, 2))]);
+if ($encoded === "users[2]{id,name}:\n  1,Ada\n  2,Bob") {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode($encoded) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 19.2 Mismatched rows throw by default
+echo "--- Testing: Row Mismatch Error ---\n";
+$tester->totalTests++;
+try {
+    toon_encode(["users" => toon_test_rows($sourceRows)]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 19.3 'skip' and 'fill' policies
+$tester->totalTests++;
+echo "--- Testing: Row Mismatch Skip And Fill ---\n";
+$skipped = toon_encode(["users" => toon_test_rows($sourceRows)], ["row_mismatch" => "skip"]);
+$filled = toon_encode(new ArrayIterator($sourceRows), ["row_mismatch" => "fill"]);
+if ($skipped === "users[2]{id,name}:\n  1,Ada\n  2,Bob"
+    && $filled === "[3]{id,name}:\n  1,Ada\n  2,Bob\n  3,null") {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode([$skipped, $filled]) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 19.4 Lengthless headers round-trip
+$tester->totalTests++;
+echo "--- Testing: Lengthless Header ---\n";
+$encoded = toon_encode(["users" => toon_test_rows(array_slice($sourceRows, 0, 2))], ["lengthless" => true]);
+$decoded = toon_decode($encoded, ["strict" => true]);
+if ($encoded === "users[]{id,name}:\n  1,Ada\n  2,Bob"
+    && $decoded === ["users" => [["id" => 1, "name" => "Ada"], ["id" => 2, "name" => "Bob"]]]) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode($encoded) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 19.5 Rows stream straight to the output with lengthless headers
+$tester->totalTests++;
+echo "--- Testing: Stream Generator Rows ---\n";
+function toon_test_many_rows(int $count): Generator {
+    for ($i = 0; $i < $count; $i++) {
+        yield ["id" => $i, "label" => "row-$i"];
+    }
+}
+$stream = fopen("php://temp", "w+");
+$before = memory_get_usage();
+$written = toon_encode_to_stream(
+    ["total" => 100000, "rows" => toon_test_many_rows(100000)],
+    $stream,
+    ["lengthless" => true]
+);
+$growth = memory_get_usage() - $before;
+rewind($stream);
+$count = 0;
+$ok = true;
+foreach (toon_decode_rows($stream, "rows") as $index => $row) {
+    $ok = $ok && $row === ["id" => $index, "label" => "row-$index"];
+    $count++;
+}
+fclose($stream);
+if ($ok && $count === 100000 && $written > 0 && $growth < 1024 * 1024) {
+    echo "✅ PASS - $count rows, $written bytes\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - $count rows, memory grew by $growth bytes\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 19.6 Exceptions thrown by the generator propagate unchanged
+echo "--- Testing: Generator Exception ---\n";
+$tester->totalTests++;
+$failing = (function () {
+    yield ["id" => 1];
+    throw new DomainException("cursor closed");
+})();
+try {
+    toon_encode(["rows" => $failing]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (DomainException $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 20: PDO STATEMENTS
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 20: PDO STATEMENTS\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+if (class_exists(PDO::class) && in_array("sqlite", PDO::getAvailableDrivers(), true)) {
+    $pdo = new PDO("sqlite::memory:");
+    $pdo->exec("CREATE TABLE users (id INTEGER, name TEXT, score REAL)");
+    $pdo->exec("INSERT INTO users VALUES (1, 'Ada', 9.5), (2, 'Bob, Jr', NULL)");
+
+    // 20.1 Rows are written as a table with the column names as fields
+    $tester->totalTests++;
+    echo "--- Testing: Encode PDO Statement ---\n";
+    $stmt = $pdo->query("SELECT id, name, score FROM users ORDER BY id");
+    $encoded = toon_encode_pdo($stmt);
+    if ($encoded === "[2]{id,name,score}:\n  1,Ada,9.5\n  2,\"Bob, Jr\",null"
+        && toon_decode($encoded) === $pdo->query("SELECT id, name, score FROM users ORDER BY id")->fetchAll(PDO::FETCH_ASSOC)) {
+        echo "✅ PASS\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Got: " . json_encode($encoded) . "\n";
+        $tester->failedTests++;
+    }
+    echo "\n";
+
+    // 20.2 Empty result sets and lengthless headers
+    $tester->totalTests++;
+    echo "--- Testing: Encode PDO Empty And Lengthless ---\n";
+    $empty = toon_encode_pdo($pdo->query("SELECT id, name FROM users WHERE id > 10"));
+    $lengthless = toon_encode_pdo(
+        $pdo->query("SELECT name FROM users ORDER BY id"),
+        ["lengthless" => true, "delimiter" => "|"]
+    );
+    if ($empty === "[0]{id,name}:" && $lengthless === "[|]{name}:\n  Ada\n  Bob, Jr") {
+        echo "✅ PASS\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Got: " . json_encode([$empty, $lengthless]) . "\n";
+        $tester->failedTests++;
+    }
+    echo "\n";
+
+    // 20.3 Statements without a result set are rejected
+    echo "--- Testing: Encode PDO Without Result Set ---\n";
+    $tester->totalTests++;
+    try {
+        toon_encode_pdo($pdo->prepare("DELETE FROM users WHERE id = 0"));
+        echo "❌ FAIL - Expected exception not thrown\n";
+        $tester->failedTests++;
+    } catch (Exception $e) {
+        echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+        $tester->passedTests++;
+    }
+    echo "\n";
+} else {
+    echo "⚠️  SKIP - pdo_sqlite is not available\n\n";
+}
+
+// ============================================================================
+// SECTION 21: PATH DECODING
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 21: PATH DECODING\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+$config = "settings:\n  cache:\n    ttl: 60\n  llm:\n    model: gpt-4o\n    stop: a, b\nusers[2]{id,name}:\n  1,Ada\n  2,Bob";
+
+// 21.1 Map keys, list indexes and table rows
+$tester->totalTests++;
+echo "--- Testing: Decode Path ---\n";
+$results = [
+    toon_decode_path($config, "settings.llm.model"),
+    toon_decode_path($config, "settings.llm.stop[1]"),
+    toon_decode_path($config, "users[1]"),
+    toon_decode_path($config, "users.0.name"),
+    toon_decode_path($config, "settings.cache"),
+];
+if ($results === ["gpt-4o", "b", ["id" => 2, "name" => "Bob"], "Ada", ["ttl" => 60]]) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode($results) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 21.2 Missing paths throw
+echo "--- Testing: Decode Missing Path ---\n";
+$tester->totalTests++;
+try {
+    toon_decode_path($config, "settings.llm.temperature");
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 22: LAZY DOCUMENTS
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 22: LAZY DOCUMENTS\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 22.1 Array access returns scalars and nested documents
+$tester->totalTests++;
+echo "--- Testing: Document Array Access ---\n";
+$doc = new Toon\Document($config);
+$llm = $doc["settings"]["llm"];
+if ($llm instanceof Toon\Document
+    && $llm["model"] === "gpt-4o"
+    && $llm["stop"][1] === "b"
+    && $doc["users"][0]["name"] === "Ada"
+    && $doc["missing"] === null
+    && isset($doc["users"]) && !isset($doc["nope"])
+    && count($doc) === 2 && count($doc["users"]) === 2) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 22.2 Iteration, toArray() and json_encode()
+$tester->totalTests++;
+echo "--- Testing: Document Iteration And Conversion ---\n";
+$keys = [];
+foreach ($doc["settings"] as $key => $value) {
+    $keys[] = $key;
+}
+$full = toon_decode($config);
+if ($keys === ["cache", "llm"]
+    && $doc->toArray() === $full
+    && $doc["users"]->toArray() === $full["users"]
+    && json_encode($doc) === json_encode($full)) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode($keys) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 22.3 Documents are read-only
+echo "--- Testing: Document Is Read-Only ---\n";
+$tester->totalTests++;
+try {
+    $doc["settings"] = [];
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected exception: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 23: DIRECT ENCODING
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 23: DIRECT ENCODING\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// toon_encode() writes PHP values directly; the "tree" option still goes
+// through the converted value, so both must agree byte for byte, and with
+// toon_encode_to_stream()
+function encodeThroughStream($value, array $options = []) {
+    $stream = fopen("php://memory", "w+");
+    toon_encode_to_stream($value, $stream, $options);
+    rewind($stream);
+    $output = stream_get_contents($stream);
+    fclose($stream);
+    return $output;
+}
+
+// 23.1 Mixed documents match the tree and stream encoders
+$tester->totalTests++;
+echo "--- Testing: Direct Encoding Matches Tree Encoding ---\n";
+$corpus = [
+    "scalar" => "plain",
+    "empty" => [],
+    "list" => [1, 2.25, true, null, "a, b", " padded ", "[x]", "line\nbreak", ""],
+    "nested lists" => [[1, 2], [], [["deep"]]],
+    "list of maps" => [["id" => 1, "name" => "Ada"], ["id" => 2, "name" => "Bob"]],
+    "sparse" => [1 => "one", 3 => "three"],
+    "map" => ["status" => OrderStatus::Pending, "when" => new DateTimeImmutable("2024-05-01T12:30:00+00:00")],
+    "rows" => new ArrayIterator([["id" => 1], ["id" => 2]]),
+    "quote\"key" => "back\\slash",
+];
+$mismatches = [];
+foreach ([[], ["indent" => 4], ["delimiter" => "|"], ["delimiter" => "\t"]] as $options) {
+    foreach ($corpus as $value) {
+        $direct = toon_encode($value, $options);
+        if ($direct !== toon_encode($value, ["tree" => true] + $options)
+            || $direct !== encodeThroughStream($value, $options)) {
+            $mismatches[] = json_encode([$options, $value]);
+        }
+    }
+    if (toon_encode($corpus, $options) !== toon_encode($corpus, ["tree" => true] + $options)) {
+        $mismatches[] = json_encode($options);
+    }
+}
+if ($mismatches === []) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Mismatches: " . implode("; ", $mismatches) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 23.2 Unsupported values are still substituted in place
+$tester->totalTests++;
+echo "--- Testing: Direct Encoding Substitutions ---\n";
+$encoded = toon_encode(["jobs" => [1, fn() => 2]], ["unsupported" => "null"]);
+$substitutions = toon_last_substitutions();
+$tree = toon_encode(["jobs" => [1, fn() => 2]], ["unsupported" => "null", "tree" => true]);
+if ($encoded === "jobs: 1, null" && $substitutions === ["jobs.1"]
+    && $tree === $encoded && toon_last_substitutions() === ["jobs.1"]) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: $encoded\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 24: DIRECT DECODING
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 24: DIRECT DECODING\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 24.1 toon_decode() builds PHP values directly; an empty path still goes
+// through the parsed tree, so both must give the same values
+$tester->totalTests++;
+echo "--- Testing: Direct Decoding Matches Tree Decoding ---\n";
+$documents = [
+    $config,
+    "[2]{id,name}:\n  1,Ada\n  2,\"Bob, Jr\"",
+    "[3]: 1, 2.5, \"x\"",
+    "list: a, [b, c], []\nquoted: \"a\\\"b\"\nempty:\n0: zero\n-1: minus",
+    "order:\n  status: pending\n  priority: 2\n  at: 2024-05-01T12:30:00Z",
+    "plain",
+    "",
+];
+$optionSets = [
+    [],
+    ["objects" => true],
+    ["enums" => ["order.status" => OrderStatus::class, "order.priority" => Priority::class]],
+    ["dates" => true],
+];
+$mismatches = [];
+foreach ($optionSets as $options) {
+    foreach ($documents as $document) {
+        $direct = toon_decode($document, $options);
+        $tree = toon_decode_path($document, "", $options);
+        if (serialize($direct) !== serialize($tree)) {
+            $mismatches[] = json_encode([$document, $options]);
+        }
+    }
+}
+if ($mismatches === []) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Mismatches: " . implode("; ", $mismatches) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 24.2 Errors raised while building values still surface as PHP exceptions
+echo "--- Testing: Direct Decoding Enum Errors ---\n";
+$tester->totalTests++;
+try {
+    toon_decode("order:\n  status: lost", ["enums" => ["order.status" => OrderStatus::class]]);
+    echo "❌ FAIL - Expected ValueError not thrown\n";
+    $tester->failedTests++;
+} catch (ValueError $e) {
+    echo "✅ PASS - Caught expected error: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 25: SHARED KEYS
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 25: SHARED KEYS\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 25.1 Rows sharing their keys decode like separately built arrays
+$tester->totalTests++;
+echo "--- Testing: Decode Rows With Shared Keys ---\n";
+$expected = [];
+$table = "rows[1000]{id,name,7}:\n";
+for ($i = 0; $i < 1000; $i++) {
+    $expected[] = ["id" => $i, "name" => "user$i", 7 => $i % 2 === 0];
+    $table .= "  $i,user$i," . ($i % 2 === 0 ? "true" : "false") . "\n";
+}
+$rows = toon_decode($table)["rows"];
+$objects = toon_decode($table, ["objects" => true])->rows;
+$rows[0]["extra"] = true;
+if (count($rows) === 1000
+    && array_slice($rows, 1) === array_slice($expected, 1)
+    && !isset($rows[1]["extra"])
+    && $objects[999]->name === "user999" && $objects[999]->{"7"} === false
+    && array_is_list($rows)) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode(array_slice($rows, 0, 2)) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 26: DUPLICATE KEYS
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 26: DUPLICATE KEYS\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+$duplicated = "a: 1\nb: 2\na: 3\nrow:\n  x: 1\n  x: 2";
+
+// 26.1 The last value wins by default, at the position of the first
+$tester->test_encode_decode(
+    "Duplicate Keys Last Wins",
+    $duplicated,
+    ["a" => 3, "b" => 2, "row" => ["x" => 2]]
+);
+
+// 26.2 'first' keeps the first value, for arrays and objects alike
+$tester->test_encode_decode(
+    "Duplicate Keys First Wins",
+    $duplicated,
+    ["a" => 1, "b" => 2, "row" => ["x" => 1]],
+    ["duplicate_keys" => "first"]
+);
+$tester->totalTests++;
+echo "--- Testing: Duplicate Keys First Wins (Objects) ---\n";
+$object = toon_decode($duplicated, ["duplicate_keys" => "first", "objects" => true]);
+if ($object->a === 1 && $object->row->x === 1) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL - Got: " . json_encode($object) . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// 26.3 'error' rejects the document
+$tester->totalTests++;
+echo "--- Testing: Duplicate Keys Error ---\n";
+try {
+    toon_decode($duplicated, ["duplicate_keys" => "error"]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected error: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 26.4 Unknown policies are rejected
+$tester->totalTests++;
+echo "--- Testing: Invalid duplicate_keys Option ---\n";
+try {
+    toon_decode("a: 1", ["duplicate_keys" => "merge"]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    echo "✅ PASS - Caught expected error: " . $e->getMessage() . "\n";
+    $tester->passedTests++;
+}
+echo "\n";
+
+// 26.5 Paths follow the same policy
+$tester->totalTests++;
+echo "--- Testing: Duplicate Keys In Paths ---\n";
+$repeated = "a: 1\nb:\n  c: 2\na: 3\nb:\n  c: 4";
+try {
+    toon_decode_path($repeated, "a", ["duplicate_keys" => "error"]);
+    $rejected = false;
+} catch (Exception $e) {
+    $rejected = $e->getCode() === TOON_ERROR_DUPLICATE_KEY;
+}
+if (toon_decode_path($repeated, "b.c", ["duplicate_keys" => "first"]) === 2
+    && toon_decode_path($repeated, "b.c") === 4
+    && $rejected) {
+    echo "✅ PASS\n";
+    $tester->passedTests++;
+} else {
+    echo "❌ FAIL\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
+// ============================================================================
+// SECTION 27: ERROR CODES
+// ============================================================================
+echo "═══════════════════════════════════════════════════════════════════\n";
+echo "SECTION 27: ERROR CODES\n";
+echo "═══════════════════════════════════════════════════════════════════\n\n";
+
+// 27.1 Each kind of parser error has its own exception code
+$errorCases = [
+    "Unexpected Indent" => ["a: 1\n  b: 2", ["strict" => true], TOON_ERROR_UNEXPECTED_INDENT],
+    "Missing Key" => ["a: 1\njunk", ["strict" => true], TOON_ERROR_MISSING_KEY],
+    "Count Mismatch" => ["tags[3]: a,b", ["strict" => true], TOON_ERROR_COUNT_MISMATCH],
+    "Invalid Escape" => ["a: \"x\\q\"", ["strict" => true], TOON_ERROR_INVALID_ESCAPE],
+    "Unterminated String" => ["a: \"open", ["strict" => true], TOON_ERROR_UNTERMINATED_STRING],
+    "Depth Exceeded" => ["a:\n  b:\n    c: 1", ["max_depth" => 1], TOON_ERROR_DEPTH_EXCEEDED],
+    "Depth Exceeded In Table" => ["users[1]{id}:\n  1", ["max_depth" => 0], TOON_ERROR_DEPTH_EXCEEDED],
+    "Depth Beyond The Cap" => ["value: " . str_repeat("[", 5000) . "x" . str_repeat("]", 5000), ["max_depth" => 1000000], TOON_ERROR_DEPTH_EXCEEDED],
+    "Duplicate Key" => ["a: 1\na: 2", ["duplicate_keys" => "error"], TOON_ERROR_DUPLICATE_KEY],
+];
+foreach ($errorCases as $name => [$input, $options, $code]) {
+    $tester->totalTests++;
+    echo "--- Testing: Error Code $name ---\n";
+    try {
+        toon_decode($input, $options);
+        echo "❌ FAIL - Expected exception not thrown\n";
+        $tester->failedTests++;
+    } catch (Exception $e) {
+        if ($e->getCode() === $code) {
+            echo "✅ PASS - Caught expected error: " . $e->getMessage() . "\n";
+            $tester->passedTests++;
+        } else {
+            echo "❌ FAIL - Got code " . $e->getCode() . ": " . $e->getMessage() . "\n";
+            $tester->failedTests++;
+        }
+    }
+    echo "\n";
+}
+
+// 27.2 Messages name the line of the error
+$tester->totalTests++;
+echo "--- Testing: Error Message Line ---\n";
+try {
+    toon_decode("user:\n  id: 1\n  id: 2", ["duplicate_keys" => "error"]);
+    echo "❌ FAIL - Expected exception not thrown\n";
+    $tester->failedTests++;
+} catch (Exception $e) {
+    if ($e->getMessage() === "Line 3: duplicate key 'id'") {
+        echo "✅ PASS\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Got: " . $e->getMessage() . "\n";
+        $tester->failedTests++;
+    }
+}
+echo "\n";
+
+// 27.3 Only maps and lists count toward max_depth, as in the Rust parser
+$tester->totalTests++;
+echo "--- Testing: Depth Limit Ignores Scalars ---\n";
+try {
+    $decoded = toon_decode("a:\n  b: 1", ["max_depth" => 1]);
+    if ($decoded === ["a" => ["b" => 1]]) {
+        echo "✅ PASS\n";
+        $tester->passedTests++;
+    } else {
+        echo "❌ FAIL - Got: " . json_encode($decoded) . "\n";
+        $tester->failedTests++;
+    }
+} catch (Exception $e) {
+    echo "❌ FAIL - " . $e->getMessage() . "\n";
+    $tester->failedTests++;
+}
+echo "\n";
+
 // ============================================================================
 // PRINT SUMMARY
 // ============================================================================